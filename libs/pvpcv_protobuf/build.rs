@@ -1,7 +1,32 @@
 use std::io::Result;
+
+/// Fields of type `bytes`, that are encoded as base64 strings in JSON
+const BYTES_FIELDS: &[&str] = &[
+    "PublicUser.id",
+    "game_id",
+    "invitation_id",
+    "peer_id",
+    "receiver_user_id",
+    "reported_by_user_id",
+    "sender_user_id",
+    "user_id",
+    "variant_id",
+];
+
 fn main() -> Result<()> {
     let mut prost_build = prost_build::Config::new();
     prost_build.protoc_arg("--experimental_allow_proto3_optional");
+    if std::env::var_os("CARGO_FEATURE_JSON").is_some() {
+        prost_build
+            .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+            .type_attribute(".", "#[serde(rename_all = \"camelCase\")]")
+            .message_attribute(".", "#[serde(default)]")
+            .field_attribute("Msg.c2s", "#[serde(flatten)]")
+            .field_attribute("Msg.s2c", "#[serde(flatten)]");
+        for field in BYTES_FIELDS {
+            prost_build.field_attribute(field, "#[serde(with = \"crate::json::base64\")]");
+        }
+    }
     prost_build.compile_protos(
         &[
             "src/server_to_client.proto",
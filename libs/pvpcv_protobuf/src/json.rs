@@ -0,0 +1,47 @@
+//! Helpers for the serde derives of the `json` feature
+
+pub mod base64 {
+    use base64::{engine::general_purpose::STANDARD, DecodeError, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// A `bytes` field, that is encoded as a base64 string like in the
+    /// canonical JSON mapping of protobuf
+    pub trait BytesField: Sized {
+        fn encode(&self) -> Option<String>;
+        fn decode(value: Option<String>) -> Result<Self, DecodeError>;
+    }
+
+    impl BytesField for Vec<u8> {
+        fn encode(&self) -> Option<String> {
+            Some(STANDARD.encode(self))
+        }
+
+        fn decode(value: Option<String>) -> Result<Self, DecodeError> {
+            value.map_or(Ok(Vec::new()), |value| STANDARD.decode(value))
+        }
+    }
+
+    impl BytesField for Option<Vec<u8>> {
+        fn encode(&self) -> Option<String> {
+            self.as_ref().map(|value| STANDARD.encode(value))
+        }
+
+        fn decode(value: Option<String>) -> Result<Self, DecodeError> {
+            value.map(|value| STANDARD.decode(value)).transpose()
+        }
+    }
+
+    pub fn serialize<T: BytesField, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.encode().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: BytesField, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let value = Option::<String>::deserialize(deserializer)?;
+        T::decode(value).map_err(D::Error::custom)
+    }
+}
@@ -1,3 +1,10 @@
+/// Version of the messages. It's raised with every change, that older clients
+/// or servers would misread.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[cfg(feature = "json")]
+mod json;
+
 pub mod client_to_server {
     include!(concat!(
         env!("OUT_DIR"),
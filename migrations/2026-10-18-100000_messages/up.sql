@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE messages (
+  id BIGSERIAL PRIMARY KEY,
+  sender_id UUID NOT NULL REFERENCES users(id),
+  receiver_id UUID NOT NULL REFERENCES users(id),
+  body VARCHAR NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  read_at TIMESTAMPTZ NULL,
+  CHECK (sender_id != receiver_id)
+);
+CREATE INDEX messages_sender_id_receiver_id_id_idx ON messages (sender_id, receiver_id, id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS games;
+DROP TABLE IF EXISTS game_invitations;
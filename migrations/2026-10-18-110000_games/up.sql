@@ -0,0 +1,31 @@
+-- Your SQL goes here
+CREATE TABLE game_invitations (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  sender_id UUID NOT NULL REFERENCES users(id),
+  receiver_id UUID NOT NULL REFERENCES users(id),
+  variant_id UUID NOT NULL,
+  variant_version VARCHAR NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'pending' CHECK (
+    status IN ('pending', 'accepted', 'declined', 'timed-out', 'cancelled')
+  ),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  resolved_at TIMESTAMPTZ NULL,
+  CHECK (sender_id != receiver_id)
+);
+CREATE INDEX game_invitations_sender_id_idx ON game_invitations (sender_id);
+CREATE INDEX game_invitations_receiver_id_idx ON game_invitations (receiver_id);
+
+CREATE TABLE games (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  invitation_id UUID NOT NULL UNIQUE REFERENCES game_invitations(id),
+  sender_id UUID NOT NULL REFERENCES users(id),
+  receiver_id UUID NOT NULL REFERENCES users(id),
+  variant_id UUID NOT NULL,
+  variant_version VARCHAR NOT NULL,
+  peer_id BYTEA NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'in-progress' CHECK (status IN ('in-progress', 'finished')),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  finished_at TIMESTAMPTZ NULL
+);
+CREATE INDEX games_sender_id_idx ON games (sender_id);
+CREATE INDEX games_receiver_id_idx ON games (receiver_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE game_invitations
+  DROP COLUMN IF EXISTS initial_secs,
+  DROP COLUMN IF EXISTS increment_secs;
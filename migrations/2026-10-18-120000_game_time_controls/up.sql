@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE game_invitations
+  ADD COLUMN initial_secs INTEGER NULL CHECK (initial_secs > 0),
+  ADD COLUMN increment_secs INTEGER NULL CHECK (increment_secs >= 0),
+  ADD CHECK ((initial_secs IS NULL) = (increment_secs IS NULL));
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS variant_versions;
+DROP TABLE IF EXISTS variants;
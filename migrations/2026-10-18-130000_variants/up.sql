@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE variants (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  name VARCHAR NOT NULL UNIQUE,
+  description VARCHAR NOT NULL DEFAULT '',
+  author_id UUID NOT NULL REFERENCES users(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE variant_versions (
+  id BIGSERIAL PRIMARY KEY,
+  variant_id UUID NOT NULL REFERENCES variants(id),
+  version VARCHAR NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  withdrawn_at TIMESTAMPTZ NULL,
+  UNIQUE (variant_id, version)
+);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS variant_versions_immutable_bundle ON variant_versions;
+DROP FUNCTION IF EXISTS forbid_variant_bundle_update();
+ALTER TABLE variant_versions
+  DROP COLUMN IF EXISTS bundle,
+  DROP COLUMN IF EXISTS bundle_format,
+  DROP COLUMN IF EXISTS checksum;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+ALTER TABLE variant_versions
+  ADD COLUMN bundle BYTEA NULL,
+  ADD COLUMN bundle_format VARCHAR NULL CHECK (bundle_format IN ('json', 'toml')),
+  ADD COLUMN checksum VARCHAR NULL,
+  ADD CHECK (
+    (bundle IS NULL AND bundle_format IS NULL AND checksum IS NULL)
+    OR (bundle IS NOT NULL AND bundle_format IS NOT NULL AND checksum IS NOT NULL)
+  );
+
+-- Clients rely on the checksum, so published bundles can't be replaced
+CREATE FUNCTION forbid_variant_bundle_update() RETURNS trigger AS $$
+BEGIN
+  IF NEW.bundle IS DISTINCT FROM OLD.bundle
+    OR NEW.bundle_format IS DISTINCT FROM OLD.bundle_format
+    OR NEW.checksum IS DISTINCT FROM OLD.checksum THEN
+    RAISE EXCEPTION 'variant bundles are immutable';
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER variant_versions_immutable_bundle BEFORE UPDATE ON variant_versions
+  FOR EACH ROW EXECUTE PROCEDURE forbid_variant_bundle_update();
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+UPDATE game_invitations SET status = 'cancelled' WHERE status = 'incompatible';
+ALTER TABLE game_invitations DROP CONSTRAINT game_invitations_status_check;
+ALTER TABLE game_invitations ADD CONSTRAINT game_invitations_status_check CHECK (
+  status IN ('pending', 'accepted', 'declined', 'timed-out', 'cancelled')
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE game_invitations DROP CONSTRAINT game_invitations_status_check;
+ALTER TABLE game_invitations ADD CONSTRAINT game_invitations_status_check CHECK (
+  status IN ('pending', 'accepted', 'declined', 'timed-out', 'cancelled', 'incompatible')
+);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS game_disputes;
+DROP TABLE IF EXISTS game_reports;
+UPDATE games SET status = 'in-progress' WHERE status = 'disputed';
+ALTER TABLE games DROP CONSTRAINT games_status_check;
+ALTER TABLE games
+  ADD CONSTRAINT games_status_check CHECK (status IN ('in-progress', 'finished')),
+  DROP COLUMN IF EXISTS outcome,
+  DROP COLUMN IF EXISTS termination;
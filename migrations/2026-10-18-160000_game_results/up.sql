@@ -0,0 +1,31 @@
+-- Your SQL goes here
+ALTER TABLE games DROP CONSTRAINT games_status_check;
+ALTER TABLE games
+  ADD CONSTRAINT games_status_check CHECK (status IN ('in-progress', 'finished', 'disputed')),
+  ADD COLUMN outcome VARCHAR NULL CHECK (
+    outcome IN ('sender-won', 'receiver-won', 'draw', 'aborted')
+  ),
+  ADD COLUMN termination VARCHAR NULL;
+
+CREATE TABLE game_reports (
+  id BIGSERIAL PRIMARY KEY,
+  game_id UUID NOT NULL REFERENCES games(id),
+  user_id UUID NOT NULL REFERENCES users(id),
+  outcome VARCHAR NOT NULL CHECK (
+    outcome IN ('sender-won', 'receiver-won', 'draw', 'aborted')
+  ),
+  termination VARCHAR NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (game_id, user_id)
+);
+
+SELECT
+  diesel_manage_updated_at('game_reports');
+
+CREATE TABLE game_disputes (
+  id BIGSERIAL PRIMARY KEY,
+  game_id UUID NOT NULL UNIQUE REFERENCES games(id),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  resolved_at TIMESTAMPTZ NULL
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS rating_history;
+DROP TABLE IF EXISTS ratings;
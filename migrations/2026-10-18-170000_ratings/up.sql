@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TABLE ratings (
+  user_id UUID NOT NULL REFERENCES users(id),
+  variant_id UUID NOT NULL REFERENCES variants(id),
+  rating DOUBLE PRECISION NOT NULL,
+  deviation DOUBLE PRECISION NOT NULL,
+  volatility DOUBLE PRECISION NOT NULL,
+  games INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, variant_id)
+);
+
+SELECT
+  diesel_manage_updated_at('ratings');
+
+CREATE TABLE rating_history (
+  id BIGSERIAL PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES users(id),
+  variant_id UUID NOT NULL REFERENCES variants(id),
+  game_id UUID NOT NULL REFERENCES games(id),
+  rating DOUBLE PRECISION NOT NULL,
+  deviation DOUBLE PRECISION NOT NULL,
+  volatility DOUBLE PRECISION NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (game_id, user_id)
+);
+CREATE INDEX rating_history_user_id_variant_id_id_idx ON rating_history (user_id, variant_id, id);
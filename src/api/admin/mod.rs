@@ -0,0 +1,105 @@
+use actix_web::web::{self, Data, Json, Path, ServiceConfig};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        auth::session::auth::Auth,
+        websocket::{
+            admin::{self, SessionInfo, UserSessions},
+            outbox::QueueMetricsSnapshot,
+            Websockets,
+        },
+    },
+    app_result::EndpointResult,
+    error::AppError,
+};
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .service(list_websocket_sessions)
+            .service(list_user_websocket_sessions)
+            .service(close_user_websocket_sessions)
+            .service(close_user_websocket_session),
+    );
+}
+
+/// The sessions held by the instance, that answers the request
+#[get("/websocket-sessions")]
+async fn list_websocket_sessions(
+    auth: Auth,
+    ws_server: Data<Websockets>,
+) -> EndpointResult<ListResponseBody> {
+    auth.should_be_admin()?;
+    let res = ListResponseBody {
+        instance_id: ws_server.cluster.instance_id,
+        sessions: ws_server.session_infos(None).await,
+        queue_metrics: ws_server.queue_metrics.snapshot(),
+    };
+    Ok(Json(res))
+}
+
+#[get("/users/{user_id}/websocket-sessions")]
+async fn list_user_websocket_sessions(
+    auth: Auth,
+    ws_server: Data<Websockets>,
+    path: Path<Uuid>,
+) -> EndpointResult<UserSessions> {
+    auth.should_be_admin()?;
+    let user_id = path.into_inner();
+    let res = admin::user_sessions(&ws_server, user_id).await?;
+    Ok(Json(res))
+}
+
+#[post("/users/{user_id}/websocket-sessions/close")]
+async fn close_user_websocket_sessions(
+    auth: Auth,
+    ws_server: Data<Websockets>,
+    path: Path<Uuid>,
+    Json(json): Json<CloseRequestBody>,
+) -> EndpointResult<CloseResponseBody> {
+    auth.should_be_admin()?;
+    let user_id = path.into_inner();
+    let ws_server = ws_server.into_inner();
+    let closed = admin::terminate_sessions_of_user(&ws_server, user_id, &json.reason).await?;
+    log::info!("{}: Closed {closed} sessions of {user_id}", auth.user_id);
+    Ok(Json(CloseResponseBody { closed }))
+}
+
+#[post("/users/{user_id}/websocket-sessions/{session_id}/close")]
+async fn close_user_websocket_session(
+    auth: Auth,
+    ws_server: Data<Websockets>,
+    path: Path<(Uuid, Uuid)>,
+    Json(json): Json<CloseRequestBody>,
+) -> EndpointResult<CloseResponseBody> {
+    auth.should_be_admin()?;
+    let (user_id, session_id) = path.into_inner();
+    let ws_server = ws_server.into_inner();
+    if !admin::terminate_session(&ws_server, user_id, session_id, &json.reason).await? {
+        return Err(AppError::WebsocketSessionNotFound);
+    }
+    log::info!("{}: Closed session {session_id} of {user_id}", auth.user_id);
+    Ok(Json(CloseResponseBody { closed: 1 }))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListResponseBody {
+    instance_id: Uuid,
+    sessions: Vec<SessionInfo>,
+    queue_metrics: QueueMetricsSnapshot,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CloseRequestBody {
+    /// Sent to the client in the close frame
+    reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CloseResponseBody {
+    closed: usize,
+}
@@ -5,8 +5,9 @@ use actix_web::{
 
 use crate::{
     api::auth::{
-        payloads::{LoginResponse, SigninPayload, SignupPayload},
+        payloads::{LoginResponse, SigninPayload, SignupPayload, WebsocketTicketResponse},
         providers::{provider::ProviderError, ProviderFactory},
+        session::{auth::Auth, ticket::TicketClaims},
         util::{generate_login_token, suggest_username},
     },
     app_result::EndpointResult,
@@ -28,6 +29,7 @@ pub fn config(cfg: &mut ServiceConfig) {
         scope("/auth")
             .service(signin)
             .service(signup)
+            .service(websocket_ticket)
             .configure(google::config)
             .configure(lichess::config),
     );
@@ -97,3 +99,18 @@ async fn signup(
     let res = LoginResponse::success(token, user);
     Ok(Json(res))
 }
+
+/// Issues a single-use ticket, with which browsers can open a websocket
+#[post("/websocket-ticket")]
+async fn websocket_ticket(
+    auth: Auth,
+    jwt_config: Data<session::Config>,
+) -> EndpointResult<WebsocketTicketResponse> {
+    let claims = TicketClaims::new(&jwt_config, auth.user_id);
+    let ticket = claims.generate_token(&jwt_config)?;
+    let res = WebsocketTicketResponse {
+        ticket,
+        expires_at: claims.exp,
+    };
+    Ok(Json(res))
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+
 use crate::db::users::User;
 
 #[derive(Debug, Clone, Serialize)]
@@ -43,3 +45,10 @@ pub struct SignupPayload {
     pub username: String,
     pub oauth_data: OauthData,
 }
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebsocketTicketResponse {
+    pub ticket: String,
+    pub expires_at: DateTime<Utc>,
+}
@@ -1,15 +1,15 @@
 use actix_web::{http::header::Header, web::Data, FromRequest};
 use actix_web_httpauth::headers::authorization::{Authorization, Bearer};
-use diesel_async::AsyncPgConnection;
 use futures::future::LocalBoxFuture;
 use uuid::Uuid;
 
-use crate::{api::auth, error::AppError, db::users::User};
+use crate::{api::auth, error::AppError};
 
 use super::{claims::Claims, config::Config};
 
 pub struct Auth {
     pub user_id: Uuid,
+    pub is_admin: bool,
 }
 impl Auth {
     pub fn is_user(&self, user_id: Uuid) -> bool {
@@ -21,14 +21,9 @@ impl Auth {
         }
         Ok(())
     }
-    pub async fn should_be_friends_with(
-        &self,
-        conn: &mut AsyncPgConnection,
-        other_user_id: Uuid,
-    ) -> Result<(), AppError> {
-        let are_friends = User::is_friends_with(conn, self.user_id, other_user_id).await?;
-        if !are_friends {
-            return Err(AppError::Unauthorized);
+    pub fn should_be_admin(&self) -> Result<(), AppError> {
+        if !self.is_admin {
+            return Err(AppError::Forbidden);
         }
         Ok(())
     }
@@ -50,12 +45,17 @@ impl FromRequest for Auth {
             let Config {
                 jwt_decoding_key,
                 jwt_validation,
+                admin_user_ids,
                 ..
             } = req.app_data::<Data<Config>>().unwrap().as_ref();
             let claims =
                 jsonwebtoken::decode::<Claims>(jwt, jwt_decoding_key, jwt_validation)?.claims;
 
-            Ok(claims.into())
+            let is_admin = admin_user_ids.contains(&claims.sub);
+            Ok(Auth {
+                is_admin,
+                ..claims.into()
+            })
         })
     }
 }
@@ -21,7 +21,10 @@ pub struct Claims {
 impl From<Claims> for Auth {
     fn from(value: Claims) -> Self {
         let Claims { sub, .. } = value;
-        Auth { user_id: sub }
+        Auth {
+            user_id: sub,
+            is_admin: false,
+        }
     }
 }
 
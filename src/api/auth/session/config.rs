@@ -1,4 +1,5 @@
 use jsonwebtoken::{DecodingKey, EncodingKey, Validation};
+use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct Config {
@@ -7,6 +8,8 @@ pub struct Config {
     pub jwt_validation: Validation,
     pub jwt_audience: Vec<String>,
     pub jwt_issuers: Vec<String>,
+    /// Users, that may use the admin endpoints
+    pub admin_user_ids: Vec<Uuid>,
 }
 
 impl Config {
@@ -31,6 +34,13 @@ impl Config {
         if !jwt_audience_vec.is_empty() {
             jwt_validation.set_audience(&jwt_audience_vec);
         }
+        let admin_user_ids = std::env::var("ADMIN_USER_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().expect("ADMIN_USER_IDS needs to be a list of uuids!"))
+            .collect::<Vec<Uuid>>();
 
         Config {
             jwt_decoding_key,
@@ -38,6 +48,7 @@ impl Config {
             jwt_validation,
             jwt_audience: jwt_audience_vec,
             jwt_issuers: jwt_issuers_vec,
+            admin_user_ids,
         }
     }
 }
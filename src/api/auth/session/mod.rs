@@ -3,6 +3,7 @@ use actix_web::web::{Data, ServiceConfig};
 pub mod auth;
 pub mod claims;
 pub mod config;
+pub mod ticket;
 
 pub type Config = config::Config;
 
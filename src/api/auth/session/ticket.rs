@@ -0,0 +1,128 @@
+use actix_web::{
+    http::header::{self, Header},
+    web::Data,
+    FromRequest,
+};
+use actix_web_httpauth::headers::authorization::{Authorization, Bearer};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::LocalBoxFuture;
+use serde_with::TimestampSeconds;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    redis_db::{extractor::RedisClient, websocket_tickets::UsedWebsocketTicket},
+};
+
+use super::{auth::Auth, config::Config};
+
+/// Audience of websocket tickets. It keeps tickets from being accepted as
+/// session tokens.
+const TICKET_AUDIENCE: &str = "websocket-ticket";
+const TICKET_TTL_SECS: i64 = 30;
+
+/// Prefix of the `Sec-WebSocket-Protocol` value, that carries a ticket
+pub const TICKET_PROTOCOL_PREFIX: &str = "ticket.";
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TicketClaims {
+    pub sub: Uuid,
+    pub aud: Vec<String>,
+    pub iss: Vec<String>,
+    /// In seconds unlike the session claims, so that the expiration is
+    /// checked while decoding
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    pub exp: DateTime<Utc>,
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    pub iat: DateTime<Utc>,
+    /// Id of the ticket, so that it can only be used once
+    pub jti: Uuid,
+}
+
+impl TicketClaims {
+    pub fn new(config: &Config, sub: Uuid) -> Self {
+        let now = Utc::now();
+        Self {
+            sub,
+            aud: vec![TICKET_AUDIENCE.to_owned()],
+            iss: config.jwt_issuers.clone(),
+            iat: now,
+            exp: now + Duration::seconds(TICKET_TTL_SECS),
+            jti: Uuid::new_v4(),
+        }
+    }
+
+    pub fn generate_token(&self, config: &Config) -> Result<String, AppError> {
+        let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256);
+        let token = jsonwebtoken::encode(&header, self, &config.jwt_encoding_key)?;
+        Ok(token)
+    }
+
+    pub fn decode(config: &Config, ticket: &str) -> Result<Self, AppError> {
+        let mut validation = config.jwt_validation.clone();
+        validation.set_audience(&[TICKET_AUDIENCE]);
+        validation.leeway = 0;
+        let claims =
+            jsonwebtoken::decode::<Self>(ticket, &config.jwt_decoding_key, &validation)?.claims;
+        Ok(claims)
+    }
+}
+
+/// Authenticates the upgrade request of a websocket. Browsers can't set the
+/// `Authorization` header there, so a ticket is accepted as a
+/// `Sec-WebSocket-Protocol` value prefixed with `ticket.` as well. It isn't
+/// read from the query, which ends up in the access log.
+pub struct WebsocketAuth {
+    pub user_id: Uuid,
+    /// The protocol, that carried the ticket. The server has to select it in
+    /// its response.
+    pub protocol: Option<String>,
+}
+
+impl FromRequest for WebsocketAuth {
+    type Error = AppError;
+
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        if Authorization::<Bearer>::parse(req).is_ok() {
+            let auth = Auth::from_request(req, payload);
+            return Box::pin(async move {
+                let Auth { user_id, .. } = auth.await?;
+                Ok(WebsocketAuth {
+                    user_id,
+                    protocol: None,
+                })
+            });
+        }
+        let redis = RedisClient::from_request(req, payload);
+        let req = req.clone();
+        Box::pin(async move {
+            let protocol = req
+                .headers()
+                .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .find(|protocol| protocol.starts_with(TICKET_PROTOCOL_PREFIX))
+                .ok_or(AppError::Unauthorized)?;
+            let ticket = &protocol[TICKET_PROTOCOL_PREFIX.len()..];
+            let config = req.app_data::<Data<Config>>().unwrap().as_ref();
+            let TicketClaims { sub, jti, .. } = TicketClaims::decode(config, ticket)?;
+
+            let RedisClient(mut redis) = redis.await?;
+            let ttl_secs = TICKET_TTL_SECS as u64;
+            if !UsedWebsocketTicket::consume(&mut redis, jti, ttl_secs).await? {
+                return Err(AppError::WebsocketTicketUsed);
+            }
+            Ok(WebsocketAuth {
+                user_id: sub,
+                protocol: Some(protocol.to_owned()),
+            })
+        })
+    }
+}
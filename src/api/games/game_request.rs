@@ -1,23 +1,103 @@
-use actix_web::{
-    web::{Data, Path},
-    HttpResponse,
-};
+use actix_web::web::{Data, Json, Path};
 use uuid::Uuid;
 
 use crate::{
-    api::auth::session::auth::Auth,
-    app_result::EndpointResultHttpResponse,
-    db::{db_conn::DbPool, extractor::DbConn, users::User},
+    api::{
+        auth::session::auth::Auth,
+        websocket::{self, InviteError, Inviter, Websockets},
+    },
+    app_result::EndpointResult,
+    db::{
+        extractor::DbConn,
+        games::{Game, GameInvitation, NewGameInvitation, TimeControl},
+    },
+    error::AppError,
 };
 
+/// Invites the receiver to a game. The receiver is asked on all of its
+/// websocket sessions and the answer is pushed to the sessions of the sender.
 #[post("/send-request/from/{sender_id}/to/{receiver_id}")]
 pub async fn send(
-    mut db: DbConn,
     auth: Auth,
+    ws_server: Data<Websockets>,
     path: Path<(Uuid, Uuid)>,
-) -> EndpointResultHttpResponse {
+    Json(json): Json<SendRequestBody>,
+) -> EndpointResult<SendResponseBody> {
     let (user_id, receiver_id) = path.into_inner();
     auth.should_be_user(user_id)?;
-    auth.should_be_friends_with(&mut db, receiver_id).await?;
-    Ok(HttpResponse::Ok().finish())
+    let SendRequestBody {
+        variant_id,
+        variant_version,
+        time_control,
+        supported_versions,
+    } = json;
+    if time_control.is_some_and(|time_control| !time_control.is_valid()) {
+        return Err(AppError::InvalidTimeControl);
+    }
+    let new_invitation = NewGameInvitation {
+        sender_id: user_id,
+        receiver_id,
+        variant_id,
+        variant_version,
+        initial_secs: time_control.map(|time_control| time_control.initial_secs),
+        increment_secs: time_control.map(|time_control| time_control.increment_secs),
+    };
+    let ws_server = ws_server.into_inner();
+    let invitation_id = websocket::invite(
+        &ws_server,
+        Inviter::Rest,
+        new_invitation,
+        supported_versions,
+    )
+    .await?
+    .map_err(|error| match error {
+        InviteError::NotFriends => AppError::NotFriends,
+        InviteError::ReceiverOffline => AppError::ReceiverOffline,
+        InviteError::ServerShutdown => AppError::ShuttingDown,
+        InviteError::UnknownVariant => AppError::UnknownVariant,
+        InviteError::VariantWithdrawn => AppError::VariantWithdrawn,
+        InviteError::DuplicateRequest => AppError::InvitationPending,
+    })?;
+    Ok(Json(SendResponseBody { invitation_id }))
+}
+
+/// Lets clients without a websocket session follow the invitation
+#[get("/invitations/{invitation_id}")]
+pub async fn get_invitation(
+    mut db: DbConn,
+    auth: Auth,
+    path: Path<Uuid>,
+) -> EndpointResult<InvitationResponseBody> {
+    let invitation = GameInvitation::get(&mut db, path.into_inner()).await?;
+    if !auth.is_user(invitation.sender_id) && !auth.is_user(invitation.receiver_id) {
+        return Err(AppError::Forbidden);
+    }
+    let game = Game::get_by_invitation(&mut db, invitation.id).await?;
+    Ok(Json(InvitationResponseBody { invitation, game }))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendRequestBody {
+    variant_id: Uuid,
+    variant_version: String,
+    /// Untimed, if it isn't set
+    time_control: Option<TimeControl>,
+    /// Other versions of the variant, the sender can play
+    #[serde(default)]
+    supported_versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendResponseBody {
+    invitation_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationResponseBody {
+    invitation: GameInvitation,
+    /// Set, once the invitation was accepted
+    game: Option<Game>,
 }
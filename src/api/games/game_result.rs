@@ -0,0 +1,63 @@
+use actix_web::web::{Data, Json, Path};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        auth::session::auth::Auth,
+        websocket::{game_results, Websockets},
+    },
+    app_result::EndpointResult,
+    db::{
+        extractor::DbConn,
+        games::{Game, PlayerResult, ReportStatus, Termination},
+    },
+    error::AppError,
+};
+
+#[get("/{game_id}")]
+pub async fn get(mut db: DbConn, auth: Auth, path: Path<Uuid>) -> EndpointResult<Game> {
+    let game = Game::get(&mut db, path.into_inner()).await?;
+    if !game.is_player(auth.user_id) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(Json(game))
+}
+
+/// Reports the result of the game as the player saw it. The result is final,
+/// once both players reported the same.
+#[post("/{game_id}/result")]
+pub async fn report(
+    mut db: DbConn,
+    auth: Auth,
+    ws_server: Data<Websockets>,
+    path: Path<Uuid>,
+    Json(json): Json<ReportRequestBody>,
+) -> EndpointResult<ReportResponseBody> {
+    let game_id = path.into_inner();
+    let ReportRequestBody {
+        result,
+        termination,
+    } = json;
+    let (game, status) =
+        Game::report_result(&mut db, game_id, auth.user_id, result, termination).await?;
+
+    let update = game_results::game_result(&game, auth.user_id, result, termination, status);
+    if let Err(err) = game_results::publish(&ws_server, &game, update, None).await {
+        log::error!("Could not publish result of {game_id}: {err}");
+    }
+    Ok(Json(ReportResponseBody { status, game }))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportRequestBody {
+    result: PlayerResult,
+    termination: Termination,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportResponseBody {
+    status: ReportStatus,
+    game: Game,
+}
@@ -1,7 +1,14 @@
 use actix_web::web::{scope, ServiceConfig};
 
 pub mod game_request;
+pub mod game_result;
 
 pub fn config(cfg: &mut ServiceConfig) {
-    cfg.service(scope("/games")).service(game_request::send);
+    cfg.service(
+        scope("/games")
+            .service(game_request::send)
+            .service(game_request::get_invitation)
+            .service(game_result::get)
+            .service(game_result::report),
+    );
 }
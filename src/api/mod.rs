@@ -1,4 +1,6 @@
+pub mod admin;
 pub mod auth;
 pub mod users;
+pub mod variants;
 pub mod websocket;
 pub mod games;
@@ -3,18 +3,26 @@ use actix_web::{
     HttpResponse,
 };
 use chrono::{DateTime, Utc};
-use diesel_async::AsyncConnection;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use p2pcv_protobuf::server_to_client::friend_request_event::Kind;
 use uuid::Uuid;
 
 use crate::{
-    api::auth::session::auth::Auth,
-    app_result::{EndpointResult, EndpointResultHttpResponse},
+    api::{
+        auth::session::auth::Auth,
+        websocket::{
+            friend_events::{self, FriendRequestChange},
+            Websockets,
+        },
+    },
+    app_result::{AppResult, EndpointResult, EndpointResultHttpResponse},
     db::{
         db_conn::DbPool,
         extractor::DbConn,
         friend_requests::{FriendRequest, NewFriendRequest},
         friends::Friends,
-        users::{PublicUser, User},
+        ratings::{RatedUser, UserRatings},
+        users::User,
     },
     error::AppError,
 };
@@ -38,7 +46,11 @@ async fn list_to(
     auth.should_be_user(user_id)?;
     let mut db = pool.get().await?;
     let query_result = FriendRequest::list_by_receiver(&mut db, user_id).await?;
-    let friend_requests = query_result.into_iter().map(|t| t.into()).collect();
+    let ratings = UserRatings::load(&mut db, query_result.iter().map(|(_, user)| user.id)).await?;
+    let friend_requests = query_result
+        .into_iter()
+        .map(|(friend_request, user)| (friend_request, ratings.rate(user)).into())
+        .collect();
     let res = ListToResponseBody {
         receiver_id: user_id,
         friend_requests,
@@ -55,7 +67,11 @@ async fn list_from(
     let user_id = path.into_inner();
     auth.should_be_user(user_id)?;
     let query_result = FriendRequest::list_by_sender(&mut db, user_id).await?;
-    let friend_requests = query_result.into_iter().map(|t| t.into()).collect();
+    let ratings = UserRatings::load(&mut db, query_result.iter().map(|(_, user)| user.id)).await?;
+    let friend_requests = query_result
+        .into_iter()
+        .map(|(friend_request, user)| (friend_request, ratings.rate(user)).into())
+        .collect();
     let res = ListFromResponseBody {
         sender_id: user_id,
         friend_requests,
@@ -67,6 +83,7 @@ async fn list_from(
 async fn send(
     mut db: DbConn,
     auth: Auth,
+    ws_server: Data<Websockets>,
     path: Path<(Uuid, Uuid)>,
     Json(json): Json<SendRequestBody>,
 ) -> EndpointResultHttpResponse {
@@ -89,7 +106,8 @@ async fn send(
         message,
     };
 
-    FriendRequest::insert(&mut db, new_friend_request).await?;
+    let friend_request = FriendRequest::insert(&mut db, new_friend_request).await?;
+    publish(&mut db, &ws_server, Kind::Created, friend_request).await;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -97,12 +115,17 @@ async fn send(
 async fn delete_by_sender(
     mut db: DbConn,
     auth: Auth,
+    ws_server: Data<Websockets>,
     path: Path<(Uuid, Uuid)>,
 ) -> EndpointResultHttpResponse {
     let (user_id, sender_id) = path.into_inner();
     auth.should_be_user(user_id)?;
 
-    FriendRequest::delete_by_user_ids(&mut db, sender_id, user_id).await?;
+    if let Some(friend_request) =
+        FriendRequest::delete_by_user_ids(&mut db, sender_id, user_id).await?
+    {
+        publish(&mut db, &ws_server, Kind::Declined, friend_request).await;
+    }
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -111,12 +134,17 @@ async fn delete_by_sender(
 async fn delete_by_receiver(
     mut db: DbConn,
     auth: Auth,
+    ws_server: Data<Websockets>,
     path: Path<(Uuid, Uuid)>,
 ) -> EndpointResultHttpResponse {
     let (user_id, receiver_id) = path.into_inner();
     auth.should_be_user(user_id)?;
 
-    FriendRequest::delete_by_user_ids(&mut db, user_id, receiver_id).await?;
+    if let Some(friend_request) =
+        FriendRequest::delete_by_user_ids(&mut db, user_id, receiver_id).await?
+    {
+        publish(&mut db, &ws_server, Kind::Withdrawn, friend_request).await;
+    }
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -125,26 +153,60 @@ async fn delete_by_receiver(
 async fn accept(
     mut db: DbConn,
     auth: Auth,
+    ws_server: Data<Websockets>,
     path: Path<(Uuid, Uuid)>,
 ) -> EndpointResultHttpResponse {
     let (user_id, sender_id) = path.into_inner();
     auth.should_be_user(user_id)?;
-    db.transaction::<_, AppError, _>(move |txn| {
-        Box::pin(async move {
-            let deleted = FriendRequest::delete_by_user_ids(txn, sender_id, user_id).await?;
-            if deleted == 0 {
-                return Err(AppError::FriendRequestDoesntExist);
-            }
-
-            Friends::insert(txn, user_id, sender_id).await?;
-            Ok(())
+    let friend_request = db
+        .transaction::<_, AppError, _>(move |txn| {
+            Box::pin(async move {
+                let friend_request = FriendRequest::delete_by_user_ids(txn, sender_id, user_id)
+                    .await?
+                    .ok_or(AppError::FriendRequestDoesntExist)?;
+
+                Friends::insert(txn, user_id, sender_id).await?;
+                Ok(friend_request)
+            })
         })
-    })
-    .await?;
+        .await?;
+    publish(&mut db, &ws_server, Kind::Accepted, friend_request).await;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Tells the live sessions of both users about the change. The change is
+/// done at this point, so failures are only logged.
+async fn publish(
+    db: &mut AsyncPgConnection,
+    ws_server: &Websockets,
+    kind: Kind,
+    friend_request: FriendRequest,
+) {
+    let result: AppResult<()> = async {
+        let FriendRequest {
+            sender_id,
+            receiver_id,
+            message,
+            created_at,
+            ..
+        } = friend_request;
+        let change = FriendRequestChange {
+            kind,
+            sender: User::get_public(db, sender_id).await?,
+            receiver: User::get_public(db, receiver_id).await?,
+            message,
+            created_at,
+        };
+        friend_events::publish_friend_request(ws_server, change).await?;
+        Ok(())
+    }
+    .await;
+    if let Err(err) = result {
+        log::error!("Could not publish friend request event: {err}");
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ListToResponseBody {
@@ -157,7 +219,7 @@ struct ToResponseBody {
     #[serde(skip_serializing_if = "Option::is_none")]
     message: Option<String>,
     created_at: DateTime<Utc>,
-    sender: PublicUser,
+    sender: RatedUser,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -173,11 +235,11 @@ struct FromResponseBody {
     #[serde(skip_serializing_if = "Option::is_none")]
     message: Option<String>,
     created_at: DateTime<Utc>,
-    receiver: PublicUser,
+    receiver: RatedUser,
 }
 
-impl From<(FriendRequest, PublicUser)> for FromResponseBody {
-    fn from((friend_request, user): (FriendRequest, PublicUser)) -> Self {
+impl From<(FriendRequest, RatedUser)> for FromResponseBody {
+    fn from((friend_request, user): (FriendRequest, RatedUser)) -> Self {
         let FriendRequest {
             message,
             created_at,
@@ -191,8 +253,8 @@ impl From<(FriendRequest, PublicUser)> for FromResponseBody {
     }
 }
 
-impl From<(FriendRequest, PublicUser)> for ToResponseBody {
-    fn from((friend_request, user): (FriendRequest, PublicUser)) -> Self {
+impl From<(FriendRequest, RatedUser)> for ToResponseBody {
+    fn from((friend_request, user): (FriendRequest, RatedUser)) -> Self {
         let FriendRequest {
             message,
             created_at,
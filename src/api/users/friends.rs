@@ -2,30 +2,49 @@ use actix_web::{
     web::{Data, Json, Path, ServiceConfig},
     HttpResponse,
 };
+use chrono::{DateTime, Utc};
+use diesel::QueryResult;
+use diesel_async::AsyncPgConnection;
 use uuid::Uuid;
 
 use crate::{
-    api::auth::session::auth::Auth,
+    api::{
+        auth::session::auth::Auth,
+        websocket::{friend_events, presence::Presence, Websockets},
+    },
     app_result::{EndpointResult, EndpointResultHttpResponse},
     db::{
-        db_conn::{DbConnection, DbPool}, extractor::DbConn, friends::{FriendEntry, Friends}, users::User
+        db_conn::{DbConnection, DbPool},
+        extractor::DbConn,
+        friends::{FriendEntry, Friends},
+        ratings::{RatedUser, UserRatings},
+        users::User,
     },
 };
 
 pub fn config(cfg: &mut ServiceConfig) {
-    cfg.service(delete).service(list);
+    cfg.service(delete).service(list).service(list_presence);
 }
 
 #[delete("/{user_id}/friends/{friend_user_id}")]
 pub async fn delete(
     mut db: DbConn,
     auth: Auth,
+    ws_server: Data<Websockets>,
     path: Path<(Uuid, Uuid)>,
 ) -> EndpointResultHttpResponse {
     let (user_id, friend_user_id) = path.into_inner();
     auth.should_be_user(user_id)?;
 
-    Friends::delete(&mut db, user_id, friend_user_id).await?;
+    let user = User::get_public(&mut db, user_id).await?;
+    let friend = User::get_public(&mut db, friend_user_id).await?;
+    let deleted = Friends::delete(&mut db, user_id, friend_user_id).await?;
+    // The friendship is over at this point, so failures are only logged
+    if deleted > 0 {
+        if let Err(err) = friend_events::publish_friend_removed(&ws_server, user, friend).await {
+            log::error!("Could not publish removed friend: {err}");
+        }
+    }
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -39,6 +58,7 @@ async fn list(
     let user_id = path.into_inner();
     auth.should_be_user(user_id)?;
     let friends = User::list_friends_by_user_id(&mut db, user_id).await?;
+    let friends = RatedFriendEntry::with_ratings(&mut db, friends).await?;
     let res = ListResponseBody { friends };
     Ok(Json(res))
 }
@@ -46,5 +66,63 @@ async fn list(
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ListResponseBody {
-    friends: Vec<FriendEntry>,
+    friends: Vec<RatedFriendEntry>,
+}
+
+/// A `FriendEntry` with the ratings of the friend
+#[derive(Clone, Debug, Serialize)]
+struct RatedFriendEntry {
+    created_at: DateTime<Utc>,
+    friend: RatedUser,
+}
+
+impl RatedFriendEntry {
+    async fn with_ratings(
+        conn: &mut AsyncPgConnection,
+        friends: Vec<FriendEntry>,
+    ) -> QueryResult<Vec<RatedFriendEntry>> {
+        let ratings = UserRatings::load(conn, friends.iter().map(|entry| entry.friend.id)).await?;
+        let friends = friends
+            .into_iter()
+            .map(|FriendEntry { created_at, friend }| RatedFriendEntry {
+                created_at,
+                friend: ratings.rate(friend),
+            })
+            .collect();
+        Ok(friends)
+    }
+}
+
+#[get("/{user_id}/friends/presence")]
+async fn list_presence(
+    mut db: DbConn,
+    auth: Auth,
+    ws_server: Data<Websockets>,
+    path: Path<Uuid>,
+) -> EndpointResult<ListPresenceResponseBody> {
+    let user_id = path.into_inner();
+    auth.should_be_user(user_id)?;
+    let friends = User::list_friends_by_user_id(&mut db, user_id).await?;
+    let friends = RatedFriendEntry::with_ratings(&mut db, friends).await?;
+    let mut res = ListPresenceResponseBody {
+        friends: Vec::with_capacity(friends.len()),
+    };
+    for entry in friends {
+        let presence = ws_server.presence_of_user(entry.friend.user.id).await?;
+        res.friends.push(FriendPresenceEntry { entry, presence });
+    }
+    Ok(Json(res))
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListPresenceResponseBody {
+    friends: Vec<FriendPresenceEntry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct FriendPresenceEntry {
+    #[serde(flatten)]
+    entry: RatedFriendEntry,
+    presence: Presence,
 }
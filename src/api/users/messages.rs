@@ -0,0 +1,103 @@
+use actix_web::{
+    web::{Data, Json, Path, Query, ServiceConfig},
+    HttpResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        auth::session::auth::Auth,
+        websocket::{chat, Websockets},
+    },
+    app_result::{EndpointResult, EndpointResultHttpResponse},
+    db::{extractor::DbConn, messages::Message, users::User},
+    error::AppError,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list).service(read);
+}
+
+#[get("/{user_id}/friends/{friend_user_id}/messages")]
+async fn list(
+    mut db: DbConn,
+    auth: Auth,
+    path: Path<(Uuid, Uuid)>,
+    query: Query<ListQuery>,
+) -> EndpointResult<ListResponseBody> {
+    let (user_id, friend_user_id) = path.into_inner();
+    auth.should_be_user(user_id)?;
+    if !User::is_friends_with(&mut db, user_id, friend_user_id).await? {
+        return Err(AppError::NotFriends);
+    }
+    let ListQuery { before, limit } = query.into_inner();
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let messages = Message::list_between(&mut db, user_id, friend_user_id, before, limit).await?;
+    let unread_count = Message::count_unread(&mut db, user_id, friend_user_id).await?;
+    // A full page might be followed by older messages
+    let next_before = match messages.last() {
+        Some(oldest) if messages.len() as i64 == limit => Some(oldest.id),
+        _ => None,
+    };
+    let res = ListResponseBody {
+        messages,
+        unread_count,
+        next_before,
+    };
+    Ok(Json(res))
+}
+
+#[post("/{user_id}/friends/{friend_user_id}/messages/read")]
+async fn read(
+    mut db: DbConn,
+    auth: Auth,
+    ws_server: Data<Websockets>,
+    path: Path<(Uuid, Uuid)>,
+    Json(json): Json<ReadRequestBody>,
+) -> EndpointResultHttpResponse {
+    let (user_id, friend_user_id) = path.into_inner();
+    auth.should_be_user(user_id)?;
+    if !User::is_friends_with(&mut db, user_id, friend_user_id).await? {
+        return Err(AppError::NotFriends);
+    }
+    let ReadRequestBody { up_to_id } = json;
+
+    let marked = Message::mark_read(&mut db, user_id, friend_user_id, up_to_id).await?;
+    if marked > 0 {
+        if let Err(err) = chat::publish_read(&ws_server, user_id, friend_user_id, up_to_id).await {
+            log::error!("Could not publish read messages: {err}");
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListQuery {
+    /// Id of the oldest message, the client already has
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListResponseBody {
+    /// Newest first
+    messages: Vec<Message>,
+    /// Messages from the friend, that weren't read yet
+    unread_count: i64,
+    /// Passed as `before` to list the older messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_before: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadRequestBody {
+    up_to_id: i64,
+}
@@ -1,10 +1,7 @@
 use crate::{
     api::auth::session::auth::Auth,
     app_result::{EndpointResult, EndpointResultHttpResponse},
-    db::{
-        db_conn::DbPool,
-        users::{PublicUser, User},
-    },
+    db::{db_conn::DbPool, ratings::RatedUser, users::User},
     error::AppError,
 };
 use actix_web::{
@@ -14,6 +11,8 @@ use actix_web::{
 use uuid::Uuid;
 pub mod friend_requests;
 pub mod friends;
+pub mod messages;
+pub mod ratings;
 
 pub fn config(cfg: &mut ServiceConfig) {
     cfg.service(
@@ -22,15 +21,18 @@ pub fn config(cfg: &mut ServiceConfig) {
             .service(delete)
             .service(get)
             .configure(friend_requests::config)
-            .configure(friends::config),
+            .configure(friends::config)
+            .configure(messages::config)
+            .configure(ratings::config),
         // .configure(peer_connections::config),
     );
 }
 
 #[get("")]
-pub async fn list(pool: Data<DbPool>) -> EndpointResult<Vec<PublicUser>> {
+pub async fn list(pool: Data<DbPool>) -> EndpointResult<Vec<RatedUser>> {
     let mut db = pool.get().await?;
-    let res = User::list(&mut db).await?;
+    let users = User::list(&mut db).await?;
+    let res = RatedUser::with_ratings(&mut db, users).await?;
     Ok(Json(res))
 }
 
@@ -0,0 +1,45 @@
+use actix_web::web::{Json, Path, ServiceConfig};
+use uuid::Uuid;
+
+use crate::{
+    app_result::EndpointResult,
+    db::{
+        extractor::DbConn,
+        ratings::{Rating, RatingHistoryEntry},
+        users::User,
+    },
+};
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list).service(history);
+}
+
+#[get("/{user_id}/ratings")]
+async fn list(mut db: DbConn, path: Path<Uuid>) -> EndpointResult<ListResponseBody> {
+    let user_id = path.into_inner();
+    // Unknown users have no ratings rather than empty ones
+    User::get_public(&mut db, user_id).await?;
+    let ratings = Rating::list_for_user(&mut db, user_id).await?;
+    Ok(Json(ListResponseBody { ratings }))
+}
+
+#[get("/{user_id}/ratings/{variant_id}/history")]
+async fn history(mut db: DbConn, path: Path<(Uuid, Uuid)>) -> EndpointResult<HistoryResponseBody> {
+    let (user_id, variant_id) = path.into_inner();
+    let history = Rating::history(&mut db, user_id, variant_id).await?;
+    Ok(Json(HistoryResponseBody { history }))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListResponseBody {
+    /// Most played variants first
+    ratings: Vec<Rating>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryResponseBody {
+    /// Oldest first
+    history: Vec<RatingHistoryEntry>,
+}
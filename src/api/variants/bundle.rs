@@ -0,0 +1,89 @@
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+use crate::{db::variants::BundleFormat, error::AppError};
+
+/// Largest accepted bundle in bytes
+pub const MAX_BUNDLE_SIZE: usize = 64 * 1024;
+/// Boards have at most 26 files, so that squares can be named
+const MAX_BOARD_SIZE: u8 = 26;
+
+/// The parts of a rule bundle, that the server checks. Everything else is
+/// left to the clients.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuleBundle {
+    board: Board,
+    pieces: Vec<Piece>,
+    starting_position: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Board {
+    files: u8,
+    ranks: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Piece {
+    name: String,
+    symbol: String,
+}
+
+impl RuleBundle {
+    fn is_valid(&self) -> bool {
+        let RuleBundle {
+            board,
+            pieces,
+            starting_position,
+        } = self;
+        (1..=MAX_BOARD_SIZE).contains(&board.files)
+            && (1..=MAX_BOARD_SIZE).contains(&board.ranks)
+            && !pieces.is_empty()
+            && pieces
+                .iter()
+                .all(|piece| !piece.name.trim().is_empty() && !piece.symbol.trim().is_empty())
+            && !starting_position.trim().is_empty()
+    }
+}
+
+/// The format of a bundle with the content type `mime`
+pub fn format_of(mime: &mime::Mime) -> Option<BundleFormat> {
+    match (mime.type_(), mime.subtype().as_str()) {
+        (mime::APPLICATION, "json") => Some(BundleFormat::Json),
+        (mime::APPLICATION | mime::TEXT, "toml") => Some(BundleFormat::Toml),
+        _ => None,
+    }
+}
+
+/// Checks, that the bundle defines the board, the pieces and the starting
+/// position
+pub fn validate(format: BundleFormat, data: &[u8]) -> Result<(), AppError> {
+    if data.len() > MAX_BUNDLE_SIZE {
+        return Err(AppError::InvalidVariantBundle);
+    }
+    let bundle: RuleBundle = match format {
+        BundleFormat::Json => parse_json(data),
+        BundleFormat::Toml => parse_toml(data),
+    }
+    .ok_or(AppError::InvalidVariantBundle)?;
+    if !bundle.is_valid() {
+        return Err(AppError::InvalidVariantBundle);
+    }
+    Ok(())
+}
+
+fn parse_json<T: DeserializeOwned>(data: &[u8]) -> Option<T> {
+    serde_json::from_slice(data).ok()
+}
+
+fn parse_toml<T: DeserializeOwned>(data: &[u8]) -> Option<T> {
+    toml::from_str(std::str::from_utf8(data).ok()?).ok()
+}
+
+/// Hex encoded SHA-256 of the bundle
+pub fn checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
@@ -0,0 +1,168 @@
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, ETag, EntityTag},
+    web::{self, Bytes, Json, Path, ServiceConfig},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    api::auth::session::auth::Auth,
+    app_result::{EndpointResult, EndpointResultHttpResponse},
+    db::{
+        extractor::DbConn,
+        variants::{Bundle, NewVariant, NewVariantVersion, Variant, VariantVersion},
+    },
+    error::AppError,
+};
+
+pub mod bundle;
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_DESCRIPTION_LENGTH: usize = 2000;
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/variants")
+            .service(list)
+            .service(create)
+            .service(get)
+            .service(publish_version)
+            .service(download_bundle)
+            .service(withdraw_version),
+    );
+}
+
+#[get("")]
+async fn list(mut db: DbConn, _auth: Auth) -> EndpointResult<Vec<Variant>> {
+    let res = Variant::list(&mut db).await?;
+    Ok(Json(res))
+}
+
+#[post("")]
+async fn create(
+    mut db: DbConn,
+    auth: Auth,
+    Json(json): Json<CreateRequestBody>,
+) -> EndpointResult<Variant> {
+    let CreateRequestBody { name, description } = json;
+    let name = name.trim().to_owned();
+    let description = description.unwrap_or_default();
+    if name.is_empty()
+        || name.chars().count() > MAX_NAME_LENGTH
+        || description.chars().count() > MAX_DESCRIPTION_LENGTH
+    {
+        return Err(AppError::InvalidVariant);
+    }
+    let new_variant = NewVariant {
+        name,
+        description,
+        author_id: auth.user_id,
+    };
+    let res = Variant::insert(&mut db, new_variant).await?;
+    Ok(Json(res))
+}
+
+#[get("/{variant_id}")]
+async fn get(mut db: DbConn, _auth: Auth, path: Path<Uuid>) -> EndpointResult<GetResponseBody> {
+    let variant = Variant::get(&mut db, path.into_inner()).await?;
+    let versions = VariantVersion::list_for_variant(&mut db, variant.id).await?;
+    Ok(Json(GetResponseBody { variant, versions }))
+}
+
+/// Publishes a version with its rule bundle, that is sent as JSON or TOML.
+/// Versions have to be higher than all versions, that were published before.
+#[post("/{variant_id}/versions/{version}")]
+async fn publish_version(
+    mut db: DbConn,
+    auth: Auth,
+    req: HttpRequest,
+    path: Path<(Uuid, String)>,
+    body: Bytes,
+) -> EndpointResult<VariantVersion> {
+    let (variant_id, version) = path.into_inner();
+    let variant = Variant::get(&mut db, variant_id).await?;
+    should_be_author(&auth, &variant)?;
+    let version =
+        semver::Version::parse(version.trim()).map_err(|_| AppError::InvalidVariantVersion)?;
+    let format = req
+        .mime_type()
+        .ok()
+        .flatten()
+        .as_ref()
+        .and_then(bundle::format_of)
+        .ok_or(AppError::InvalidVariantBundle)?;
+    bundle::validate(format, &body)?;
+    let new_version = NewVariantVersion {
+        variant_id: variant.id,
+        version: version.to_string(),
+        checksum: bundle::checksum(&body),
+        bundle: body.to_vec(),
+        bundle_format: format,
+    };
+    let res = VariantVersion::publish(&mut db, new_version)
+        .await?
+        .ok_or(AppError::InvalidVariantVersion)?;
+    Ok(Json(res))
+}
+
+/// Downloads the rule bundle of a version. The ETag is its checksum.
+#[get("/{variant_id}/versions/{version}/bundle")]
+async fn download_bundle(
+    mut db: DbConn,
+    _auth: Auth,
+    path: Path<(Uuid, String)>,
+) -> EndpointResultHttpResponse {
+    let (variant_id, version) = path.into_inner();
+    let Bundle {
+        data,
+        format,
+        checksum,
+    } = VariantVersion::get_bundle(&mut db, variant_id, &version)
+        .await?
+        .ok_or(AppError::UnknownVariant)?;
+    Ok(HttpResponse::Ok()
+        .content_type(format.mime())
+        .insert_header(ETag(EntityTag::new_strong(checksum)))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ]))
+        .body(data))
+}
+
+#[post("/{variant_id}/versions/{version}/withdraw")]
+async fn withdraw_version(
+    mut db: DbConn,
+    auth: Auth,
+    path: Path<(Uuid, String)>,
+) -> EndpointResult<VariantVersion> {
+    let (variant_id, version) = path.into_inner();
+    let variant = Variant::get(&mut db, variant_id).await?;
+    should_be_author(&auth, &variant)?;
+    let res = VariantVersion::withdraw(&mut db, variant.id, &version)
+        .await?
+        .ok_or(AppError::UnknownVariant)?;
+    Ok(Json(res))
+}
+
+fn should_be_author(auth: &Auth, variant: &Variant) -> Result<(), AppError> {
+    if auth.is_user(variant.author_id) {
+        return Ok(());
+    }
+    auth.should_be_admin()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateRequestBody {
+    name: String,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetResponseBody {
+    variant: Variant,
+    /// Highest first
+    versions: Vec<VariantVersion>,
+}
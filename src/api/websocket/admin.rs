@@ -0,0 +1,150 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use actix_ws::{CloseCode, CloseReason};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::redis_db::websocket_sessions::WebsocketSessionEntry;
+
+use super::{close_session, cluster::ClusterMessage, WebsocketError, WebsocketSession, Websockets};
+
+/// Close reasons have to fit into a control frame
+const MAX_CLOSE_REASON_BYTES: usize = 120;
+
+/// What admins get to see of a session
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub connected_at: DateTime<Utc>,
+    pub last_pinged: Option<DateTime<Utc>>,
+    /// Whether the session is attached to a connection
+    pub attached: bool,
+    pub queue_depth: usize,
+}
+
+impl WebsocketSession {
+    async fn info(&self) -> SessionInfo {
+        let outbox = self.outbox.lock().await;
+        let last_pinged = self.last_pinged.load(Ordering::Relaxed);
+        SessionInfo {
+            id: self.id,
+            user_id: self.user_id,
+            connected_at: self.connected_at,
+            last_pinged: DateTime::from_timestamp(last_pinged as i64, 0),
+            attached: outbox.session().is_some(),
+            queue_depth: outbox.queue_depth(),
+        }
+    }
+}
+
+impl Websockets {
+    /// The sessions on this instance, optionally only the ones of a user
+    pub async fn session_infos(&self, user_id: Option<Uuid>) -> Vec<SessionInfo> {
+        let sessions: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|entry| user_id.is_none_or(|user_id| entry.user_id == user_id))
+            .map(|entry| entry.value().clone())
+            .collect();
+        let mut infos = Vec::with_capacity(sessions.len());
+        for ws_session in sessions {
+            infos.push(ws_session.info().await);
+        }
+        infos
+    }
+}
+
+/// The sessions of a user in the cluster
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSessions {
+    pub local: Vec<SessionInfo>,
+    /// Sessions on other instances, that are only known by their entries
+    pub remote: Vec<WebsocketSessionEntry>,
+}
+
+pub async fn user_sessions(
+    ws_server: &Websockets,
+    user_id: Uuid,
+) -> Result<UserSessions, WebsocketError> {
+    Ok(UserSessions {
+        local: ws_server.session_infos(Some(user_id)).await,
+        remote: ws_server.remote_sessions_of_user(user_id).await?,
+    })
+}
+
+/// Closes a session of the user on any instance for good. Returns false, if
+/// the user has no such session.
+pub async fn terminate_session(
+    ws_server: &Arc<Websockets>,
+    user_id: Uuid,
+    session_id: Uuid,
+    reason: &str,
+) -> Result<bool, WebsocketError> {
+    let Some(entry) = ws_server
+        .sessions_of_user(user_id)
+        .await?
+        .into_iter()
+        .find(|entry| entry.session_id == session_id)
+    else {
+        return Ok(false);
+    };
+    terminate(ws_server, entry, reason).await?;
+    Ok(true)
+}
+
+/// Closes all sessions of the user on all instances for good. Returns how
+/// many there were.
+pub async fn terminate_sessions_of_user(
+    ws_server: &Arc<Websockets>,
+    user_id: Uuid,
+    reason: &str,
+) -> Result<usize, WebsocketError> {
+    let entries = ws_server.sessions_of_user(user_id).await?;
+    for entry in &entries {
+        terminate(ws_server, *entry, reason).await?;
+    }
+    Ok(entries.len())
+}
+
+async fn terminate(
+    ws_server: &Arc<Websockets>,
+    entry: WebsocketSessionEntry,
+    reason: &str,
+) -> Result<(), WebsocketError> {
+    if entry.instance_id != ws_server.cluster.instance_id {
+        let message = ClusterMessage::CloseSession {
+            session_id: entry.session_id,
+            reason: reason.to_owned(),
+        };
+        return ws_server.cluster.publish(entry.instance_id, &message).await;
+    }
+    terminate_local(ws_server, entry.session_id, reason).await;
+    Ok(())
+}
+
+/// Closes a session of this instance for good
+pub(super) async fn terminate_local(ws_server: &Arc<Websockets>, session_id: Uuid, reason: &str) {
+    let Some(ws_session) = ws_server
+        .sessions
+        .get(&session_id)
+        .map(|entry| entry.value().clone())
+    else {
+        return;
+    };
+    let WebsocketSession { id, user_id, .. } = ws_session;
+    log::info!("Session {id}: Terminated by an admin: {reason} (User Id: {user_id})");
+    let mut end = reason.len().min(MAX_CLOSE_REASON_BYTES);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    let reason = CloseReason {
+        code: CloseCode::Policy,
+        description: Some(reason[..end].to_owned()),
+    };
+    close_session(ws_server, ws_session, Some(reason))
+        .await
+        .ok();
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use p2pcv_protobuf::{
+    client_to_server::SendChatMessage,
+    server_to_client::{msg::S2c, ChatMessage, ChatRead},
+};
+use uuid::Uuid;
+
+use crate::db::{
+    messages::{Message, NewMessage},
+    users::User,
+};
+
+use super::{WebsocketError, WebsocketSession, Websockets};
+
+/// Longest body of a message in characters
+const MAX_BODY_LENGTH: usize = 2000;
+
+impl From<Message> for ChatMessage {
+    fn from(value: Message) -> Self {
+        let Message {
+            id,
+            sender_id,
+            receiver_id,
+            body,
+            created_at,
+            ..
+        } = value;
+        ChatMessage {
+            id,
+            sender_user_id: sender_id.as_bytes().to_vec(),
+            receiver_user_id: receiver_id.as_bytes().to_vec(),
+            body,
+            created_at: created_at.timestamp_millis(),
+        }
+    }
+}
+
+/// Stores the message and relays it to the sessions of both users
+pub async fn handle_send(
+    ws_server: &Arc<Websockets>,
+    ws_session: &WebsocketSession,
+    request_id: i32,
+    send: SendChatMessage,
+) -> Result<(), WebsocketError> {
+    let WebsocketSession { id, user_id, .. } = ws_session;
+    let SendChatMessage {
+        receiver_user_id,
+        body,
+    } = send;
+    let receiver_id = Uuid::from_slice(&receiver_user_id)?;
+    if body.trim().is_empty() || body.chars().count() > MAX_BODY_LENGTH {
+        return Err(WebsocketError::InvalidChatMessage);
+    }
+
+    let mut db = ws_server.pool.get().await?;
+    if !User::is_friends_with(&mut db, *user_id, receiver_id).await? {
+        return Err(WebsocketError::NotFriends);
+    }
+    let new_message = NewMessage {
+        sender_id: *user_id,
+        receiver_id,
+        body,
+    };
+    let message = ChatMessage::from(Message::insert(&mut db, new_message).await?);
+    drop(db);
+
+    ws_session
+        .reply(request_id, S2c::ChatMessage(message.clone()))
+        .await?;
+    // The message is stored at this point, so failures are only logged
+    let relayed = async {
+        for entry in ws_server.sessions_of_user(*user_id).await? {
+            if entry.session_id == *id {
+                continue;
+            }
+            let s2c = S2c::ChatMessage(message.clone());
+            if let Err(err) = ws_server.send_to(entry, None, s2c).await {
+                log::debug!("Session {}: Could not push: {err}", entry.session_id);
+            }
+        }
+        ws_server
+            .push_to_user(receiver_id, S2c::ChatMessage(message))
+            .await
+    };
+    if let Err(err) = relayed.await {
+        log::error!("Session {id}: Could not relay message: {err} (User Id: {user_id})");
+    }
+    Ok(())
+}
+
+/// Tells the sessions of the sender, that the receiver read the messages up
+/// to `up_to_id`
+pub async fn publish_read(
+    ws_server: &Websockets,
+    receiver_id: Uuid,
+    sender_id: Uuid,
+    up_to_id: i64,
+) -> Result<(), WebsocketError> {
+    let read = ChatRead {
+        user_id: receiver_id.as_bytes().to_vec(),
+        up_to_id,
+    };
+    ws_server.push_to_user(sender_id, S2c::ChatRead(read)).await
+}
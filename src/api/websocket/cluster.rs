@@ -0,0 +1,445 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use p2pcv_protobuf::{
+    client_to_server::NewGameEventResponse,
+    server_to_client::{self, msg::S2c, new_game_event_response_error, NewGameEventResponseError},
+};
+use prost::Message;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use serde_with::base64::Base64;
+use uuid::Uuid;
+
+use crate::redis_db::{
+    pending_invitations::InvitationInstance, presence::PublishedPresence,
+    websocket_sessions::WebsocketSessionEntry,
+};
+
+use super::{
+    admin,
+    invitations::{InvitationAnswer, InvitationKey},
+    signaling::AcceptedGame,
+    WebsocketError, Websockets,
+};
+
+/// How long to wait before subscribing again, after the connection to redis
+/// was lost
+const RESUBSCRIBE_DELAY_SECS: u64 = 5;
+
+/// Connects the instances of the server, so that sessions on different
+/// instances can reach each other. Every instance publishes the sessions it
+/// holds in redis and listens on its own pub/sub channel.
+pub struct Cluster {
+    pub instance_id: Uuid,
+    client: redis::Client,
+    conn: MultiplexedConnection,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("instance_id", &self.instance_id)
+            .finish()
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum ClusterMessage {
+    /// Sends a message to a session of the receiving instance
+    #[serde(rename_all = "camelCase")]
+    Send {
+        session_id: Uuid,
+        /// Id of the client message, that is answered
+        reply_to: Option<i32>,
+        /// Encoded `server_to_client::Msg`
+        #[serde_as(as = "Base64")]
+        msg: Vec<u8>,
+    },
+    /// Answer to an invitation, that is pending on the receiving instance
+    #[serde(rename_all = "camelCase")]
+    InvitationAnswer {
+        key: InvitationKey,
+        session: WebsocketSessionEntry,
+        request_id: i32,
+        /// Encoded `NewGameEventResponse`
+        #[serde_as(as = "Base64")]
+        response: Vec<u8>,
+    },
+    #[serde(rename_all = "camelCase")]
+    GameAccepted {
+        game_id: Uuid,
+        #[serde_as(as = "Base64")]
+        peer_id: Vec<u8>,
+        game: AcceptedGame,
+    },
+    /// The game is over, so its sessions stop signaling
+    #[serde(rename_all = "camelCase")]
+    GameOver { game_id: Uuid },
+    /// Closes a session of the receiving instance for good
+    #[serde(rename_all = "camelCase")]
+    CloseSession { session_id: Uuid, reason: String },
+}
+
+impl Cluster {
+    pub async fn connect(client: redis::Client) -> redis::RedisResult<Self> {
+        let conn = client.get_multiplexed_async_connection().await?;
+        Ok(Cluster {
+            instance_id: Uuid::new_v4(),
+            client,
+            conn,
+        })
+    }
+
+    /// Identifies a session of this instance in the cluster
+    pub fn entry(&self, session_id: Uuid) -> WebsocketSessionEntry {
+        WebsocketSessionEntry {
+            instance_id: self.instance_id,
+            session_id,
+        }
+    }
+
+    pub async fn register(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        last_active: DateTime<Utc>,
+    ) -> Result<(), WebsocketError> {
+        self.entry(session_id)
+            .upsert(&mut self.conn.clone(), user_id, last_active)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unregister(&self, session_id: Uuid, user_id: Uuid) -> Result<(), WebsocketError> {
+        self.entry(session_id)
+            .remove(&mut self.conn.clone(), user_id)
+            .await?;
+        Ok(())
+    }
+
+    /// Sessions of the user on other instances, most recent first
+    pub async fn remote_sessions_of_user(
+        &self,
+        user_id: Uuid,
+        max_age: chrono::Duration,
+    ) -> Result<Vec<WebsocketSessionEntry>, WebsocketError> {
+        let dropoff = Utc::now() - max_age;
+        let entries =
+            WebsocketSessionEntry::list_for_user(&mut self.conn.clone(), user_id, dropoff).await?;
+        let entries = entries
+            .into_iter()
+            .filter(|entry| entry.instance_id != self.instance_id)
+            .collect();
+        Ok(entries)
+    }
+
+    /// When any session of the user on any instance was last active
+    pub async fn last_active_of_user(
+        &self,
+        user_id: Uuid,
+        max_age: chrono::Duration,
+    ) -> Result<Option<DateTime<Utc>>, WebsocketError> {
+        let dropoff = Utc::now() - max_age;
+        let last_active =
+            WebsocketSessionEntry::last_active_of_user(&mut self.conn.clone(), user_id, dropoff)
+                .await?;
+        Ok(last_active)
+    }
+
+    /// Remembers, that this instance waits for the answer to the invitation
+    pub async fn register_invitation(
+        &self,
+        key: &InvitationKey,
+        ttl: chrono::Duration,
+    ) -> Result<(), WebsocketError> {
+        let ttl_secs = ttl.num_seconds().max(1) as u64;
+        InvitationInstance::set(
+            &mut self.conn.clone(),
+            key.sender_id,
+            key.receiver_id,
+            key.request_id,
+            self.instance_id,
+            ttl_secs,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn unregister_invitation(&self, key: &InvitationKey) -> Result<(), WebsocketError> {
+        InvitationInstance::remove(
+            &mut self.conn.clone(),
+            key.sender_id,
+            key.receiver_id,
+            key.request_id,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The instance, that waits for the answer to the invitation
+    pub async fn invitation_instance(
+        &self,
+        key: &InvitationKey,
+    ) -> Result<Option<Uuid>, WebsocketError> {
+        let instance_id = InvitationInstance::get(
+            &mut self.conn.clone(),
+            key.sender_id,
+            key.receiver_id,
+            key.request_id,
+        )
+        .await?;
+        Ok(instance_id)
+    }
+
+    /// Remembers the presence, that was published for the user. Returns the
+    /// previous one.
+    pub async fn swap_published_presence(
+        &self,
+        user_id: Uuid,
+        presence: &str,
+        ttl: chrono::Duration,
+    ) -> Result<Option<String>, WebsocketError> {
+        let ttl_secs = ttl.num_seconds().max(1) as u64;
+        let previous =
+            PublishedPresence::swap(&mut self.conn.clone(), user_id, presence, ttl_secs).await?;
+        Ok(previous)
+    }
+
+    pub async fn publish(
+        &self,
+        instance_id: Uuid,
+        message: &ClusterMessage,
+    ) -> Result<(), WebsocketError> {
+        let payload = serde_json::to_vec(message)?;
+        let _: u64 = self
+            .conn
+            .clone()
+            .publish(channel(instance_id), payload)
+            .await?;
+        Ok(())
+    }
+
+    /// Sends `s2c` to a session on another instance
+    pub async fn send(
+        &self,
+        entry: WebsocketSessionEntry,
+        reply_to: Option<i32>,
+        s2c: S2c,
+    ) -> Result<(), WebsocketError> {
+        let msg = server_to_client::Msg {
+            id: 0,
+            s2c: Some(s2c),
+            seq: 0,
+        };
+        let message = ClusterMessage::Send {
+            session_id: entry.session_id,
+            reply_to,
+            msg: msg.encode_to_vec(),
+        };
+        self.publish(entry.instance_id, &message).await
+    }
+}
+
+fn channel(instance_id: Uuid) -> String {
+    format!("websocket_instances:{instance_id}")
+}
+
+/// Listens for messages of other instances
+pub fn spawn(ws_server: Arc<Websockets>) {
+    actix_web::rt::spawn(async move {
+        loop {
+            if let Err(err) = subscribe(&ws_server).await {
+                log::error!("Cluster subscription failed: {err}");
+            }
+            tokio::time::sleep(Duration::from_secs(RESUBSCRIBE_DELAY_SECS)).await;
+        }
+    });
+}
+
+async fn subscribe(ws_server: &Arc<Websockets>) -> Result<(), WebsocketError> {
+    let cluster = &ws_server.cluster;
+    let mut pubsub = cluster.client.get_async_pubsub().await?;
+    pubsub.subscribe(channel(cluster.instance_id)).await?;
+    let mut messages = pubsub.into_on_message();
+    while let Some(msg) = messages.next().await {
+        let result = match serde_json::from_slice(msg.get_payload_bytes()) {
+            Ok(message) => handle_cluster_message(ws_server, message).await,
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = result {
+            log::debug!("Could not handle cluster message: {err}");
+        }
+    }
+    Ok(())
+}
+
+async fn handle_cluster_message(
+    ws_server: &Arc<Websockets>,
+    message: ClusterMessage,
+) -> Result<(), WebsocketError> {
+    match message {
+        ClusterMessage::Send {
+            session_id,
+            reply_to,
+            msg,
+        } => {
+            let server_to_client::Msg { s2c: Some(s2c), .. } =
+                server_to_client::Msg::decode(msg.as_slice())?
+            else {
+                return Ok(());
+            };
+            let entry = ws_server.cluster.entry(session_id);
+            ws_server.send_to(entry, reply_to, s2c).await?;
+        }
+        ClusterMessage::InvitationAnswer {
+            key,
+            session,
+            request_id,
+            response,
+        } => {
+            let response = NewGameEventResponse::decode(response.as_slice())?;
+            let answer = InvitationAnswer { session, response };
+            if let Err(error) = ws_server.pending_invitations.answer(&key, answer) {
+                let error: new_game_event_response_error::Error = error.into();
+                let response_error = NewGameEventResponseError {
+                    sender_user_id: key.sender_id.as_bytes().to_vec(),
+                    request_id: key.request_id,
+                    error: error as i32,
+                };
+                let s2c = S2c::NewGameEventResponseError(response_error);
+                ws_server.send_to(session, Some(request_id), s2c).await?;
+            }
+        }
+        ClusterMessage::GameAccepted {
+            game_id,
+            peer_id,
+            game,
+        } => {
+            ws_server.accepted_games.insert(game_id, peer_id, game);
+        }
+        ClusterMessage::GameOver { game_id } => {
+            ws_server.accepted_games.remove(game_id);
+        }
+        ClusterMessage::CloseSession { session_id, reason } => {
+            admin::terminate_local(ws_server, session_id, &reason).await;
+        }
+    }
+    Ok(())
+}
+
+/// These need a redis at `REDIS_URL`, e.g. `cargo test -- --ignored`
+#[cfg(test)]
+mod tests {
+    use p2pcv_protobuf::server_to_client::ChatRead;
+
+    use super::*;
+
+    async fn cluster() -> Cluster {
+        let redis_url =
+            std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_owned());
+        let client = redis::Client::open(redis_url).unwrap();
+        Cluster::connect(client).await.unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a local redis"]
+    async fn registered_sessions_are_listed_on_other_instances() {
+        let (a, b) = (cluster().await, cluster().await);
+        let (user_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let max_age = chrono::Duration::minutes(1);
+
+        a.register(session_id, user_id, Utc::now()).await.unwrap();
+        let remote = b.remote_sessions_of_user(user_id, max_age).await.unwrap();
+        assert_eq!(remote, vec![a.entry(session_id)]);
+        // Own sessions aren't remote
+        let own = a.remote_sessions_of_user(user_id, max_age).await.unwrap();
+        assert!(own.is_empty());
+        assert!(b
+            .last_active_of_user(user_id, max_age)
+            .await
+            .unwrap()
+            .is_some());
+
+        a.unregister(session_id, user_id).await.unwrap();
+        let remote = b.remote_sessions_of_user(user_id, max_age).await.unwrap();
+        assert!(remote.is_empty());
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a local redis"]
+    async fn stale_sessions_are_not_listed() {
+        let (a, b) = (cluster().await, cluster().await);
+        let (user_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let max_age = chrono::Duration::minutes(1);
+
+        let last_active = Utc::now() - chrono::Duration::minutes(10);
+        a.register(session_id, user_id, last_active).await.unwrap();
+        let remote = b.remote_sessions_of_user(user_id, max_age).await.unwrap();
+        assert!(remote.is_empty());
+        assert!(b
+            .last_active_of_user(user_id, max_age)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a local redis"]
+    async fn invitations_are_found_on_other_instances() {
+        let (a, b) = (cluster().await, cluster().await);
+        let key = InvitationKey {
+            sender_id: Uuid::new_v4(),
+            receiver_id: Uuid::new_v4(),
+            request_id: 3,
+        };
+
+        assert_eq!(b.invitation_instance(&key).await.unwrap(), None);
+        a.register_invitation(&key, chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        let instance_id = b.invitation_instance(&key).await.unwrap();
+        assert_eq!(instance_id, Some(a.instance_id));
+
+        a.unregister_invitation(&key).await.unwrap();
+        assert_eq!(b.invitation_instance(&key).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a local redis"]
+    async fn messages_are_routed_to_the_instance_of_the_session() {
+        let (a, b) = (cluster().await, cluster().await);
+        let mut pubsub = b.client.get_async_pubsub().await.unwrap();
+        pubsub.subscribe(channel(b.instance_id)).await.unwrap();
+        let mut messages = pubsub.into_on_message();
+
+        let session_id = Uuid::new_v4();
+        let read = ChatRead {
+            user_id: Uuid::new_v4().as_bytes().to_vec(),
+            up_to_id: 42,
+        };
+        a.send(b.entry(session_id), Some(7), S2c::ChatRead(read.clone()))
+            .await
+            .unwrap();
+
+        let msg = tokio::time::timeout(Duration::from_secs(5), messages.next())
+            .await
+            .unwrap()
+            .unwrap();
+        let message: ClusterMessage = serde_json::from_slice(msg.get_payload_bytes()).unwrap();
+        let ClusterMessage::Send {
+            session_id: received_session_id,
+            reply_to,
+            msg,
+        } = message
+        else {
+            panic!("Expected a send message");
+        };
+        assert_eq!(received_session_id, session_id);
+        assert_eq!(reply_to, Some(7));
+        let msg = server_to_client::Msg::decode(msg.as_slice()).unwrap();
+        assert_eq!(msg.s2c, Some(S2c::ChatRead(read)));
+    }
+}
@@ -0,0 +1,223 @@
+use std::{str::FromStr, time::Duration};
+
+use super::rate_limit::RequestKind;
+
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_PING_INTERVAL_SECS: u64 = 20;
+const DEFAULT_AWAY_AFTER_SECS: u64 = 45;
+const DEFAULT_HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_RESUME_GRACE_SECS: u64 = 60;
+const DEFAULT_RESUME_BUFFER_SIZE: usize = 100;
+const DEFAULT_OUTBOUND_QUEUE_SIZE: usize = 64;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024;
+const DEFAULT_INVITATION_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_SESSION_LIMITS: KindLimits = KindLimits {
+    new_game: Limit::new(5, 10),
+    new_game_event_response: Limit::new(10, 30),
+    // Candidates come in bursts while a connection is negotiated
+    signaling: Limit::new(200, 600),
+    hello: Limit::new(5, 10),
+    chat_message: Limit::new(10, 60),
+    game_result: Limit::new(5, 10),
+    invalid: Limit::new(5, 30),
+};
+const DEFAULT_USER_LIMITS: KindLimits = KindLimits {
+    new_game: Limit::new(10, 20),
+    new_game_event_response: Limit::new(20, 60),
+    signaling: Limit::new(400, 1200),
+    hello: Limit::new(10, 20),
+    chat_message: Limit::new(20, 120),
+    game_result: Limit::new(10, 20),
+    invalid: Limit::new(10, 60),
+};
+const DEFAULT_STRIKES: Limit = Limit::new(10, 6);
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Sessions, that didn't send anything for this long, are closed
+    pub idle_timeout: Duration,
+    /// How often the server pings the sessions and looks for idle ones
+    pub ping_interval: Duration,
+    /// Users, whose sessions didn't respond for this long, are shown as away
+    pub away_after: Duration,
+    /// How long a new connection has to send its `Hello`
+    pub handshake_timeout: Duration,
+    /// How long a session can be resumed after its connection dropped
+    pub resume_grace_period: Duration,
+    /// How many sent messages are kept per session to replay them on resume
+    pub resume_buffer_size: usize,
+    /// How many messages may wait to be written to a connection
+    pub outbound_queue_size: usize,
+    /// Whether clients can use JSON in text frames instead of protobuf
+    pub json_encoding: bool,
+    /// How long a shutdown waits for pending invitations and for the close
+    /// frames to be flushed
+    pub shutdown_timeout: Duration,
+    /// Larger frames close the session
+    pub max_frame_size: usize,
+    /// How long the receiver has to answer an invitation
+    pub invitation_timeout: Duration,
+    pub rate_limits: RateLimits,
+}
+
+/// Allows `burst` requests at once and refills at `per_minute`
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Limit {
+    pub const fn new(burst: u32, per_minute: u32) -> Self {
+        Limit { burst, per_minute }
+    }
+}
+
+/// A limit for every kind of client message
+#[derive(Debug, Clone, Copy)]
+pub struct KindLimits {
+    pub new_game: Limit,
+    pub new_game_event_response: Limit,
+    pub signaling: Limit,
+    pub hello: Limit,
+    pub chat_message: Limit,
+    pub game_result: Limit,
+    /// Messages, that can't be handled, e.g. because they don't decode
+    pub invalid: Limit,
+}
+
+impl KindLimits {
+    pub fn get(&self, kind: RequestKind) -> Limit {
+        match kind {
+            RequestKind::NewGame => self.new_game,
+            RequestKind::NewGameEventResponse => self.new_game_event_response,
+            RequestKind::Signaling => self.signaling,
+            RequestKind::Hello => self.hello,
+            RequestKind::ChatMessage => self.chat_message,
+            RequestKind::GameResult => self.game_result,
+            RequestKind::Invalid => self.invalid,
+        }
+    }
+
+    /// Reads the limits from `WEBSOCKET_RATE_LIMIT_{scope}_{kind}`, e.g.
+    /// `WEBSOCKET_RATE_LIMIT_SESSION_NEW_GAME=5/10`
+    fn from_env(scope: &str, default: KindLimits) -> Self {
+        let limit_from_env = |kind: &str, default| {
+            limit_from_env(&format!("WEBSOCKET_RATE_LIMIT_{scope}_{kind}"), default)
+        };
+        KindLimits {
+            new_game: limit_from_env("NEW_GAME", default.new_game),
+            new_game_event_response: limit_from_env(
+                "NEW_GAME_EVENT_RESPONSE",
+                default.new_game_event_response,
+            ),
+            signaling: limit_from_env("SIGNALING", default.signaling),
+            hello: limit_from_env("HELLO", default.hello),
+            chat_message: limit_from_env("CHAT_MESSAGE", default.chat_message),
+            game_result: limit_from_env("GAME_RESULT", default.game_result),
+            invalid: limit_from_env("INVALID", default.invalid),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    pub session: KindLimits,
+    /// Shared by all sessions of a user on this instance
+    pub user: KindLimits,
+    /// How often a session may exceed its limits, before it's closed
+    pub strikes: Limit,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let idle_timeout = secs_from_env("WEBSOCKET_IDLE_TIMEOUT_SECS", DEFAULT_IDLE_TIMEOUT_SECS);
+        // The interval of the reaper panics on zero
+        let ping_interval =
+            positive_secs_from_env("WEBSOCKET_PING_INTERVAL_SECS", DEFAULT_PING_INTERVAL_SECS);
+        let away_after = secs_from_env("WEBSOCKET_AWAY_AFTER_SECS", DEFAULT_AWAY_AFTER_SECS);
+        let handshake_timeout = secs_from_env(
+            "WEBSOCKET_HANDSHAKE_TIMEOUT_SECS",
+            DEFAULT_HANDSHAKE_TIMEOUT_SECS,
+        );
+        let resume_grace_period =
+            secs_from_env("WEBSOCKET_RESUME_GRACE_SECS", DEFAULT_RESUME_GRACE_SECS);
+        let resume_buffer_size =
+            number_from_env("WEBSOCKET_RESUME_BUFFER_SIZE", DEFAULT_RESUME_BUFFER_SIZE);
+        let outbound_queue_size =
+            number_from_env("WEBSOCKET_OUTBOUND_QUEUE_SIZE", DEFAULT_OUTBOUND_QUEUE_SIZE);
+        // Only meant for debugging, so it's off in release builds by default
+        let json_encoding = std::env::var("WEBSOCKET_JSON_ENCODING")
+            .map(|enabled| {
+                enabled
+                    .parse()
+                    .expect("WEBSOCKET_JSON_ENCODING needs to be true or false!")
+            })
+            .unwrap_or(cfg!(debug_assertions));
+        let shutdown_timeout = secs_from_env(
+            "WEBSOCKET_SHUTDOWN_TIMEOUT_SECS",
+            DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+        );
+        let max_frame_size = number_from_env("WEBSOCKET_MAX_FRAME_SIZE", DEFAULT_MAX_FRAME_SIZE);
+        let invitation_timeout = secs_from_env(
+            "WEBSOCKET_INVITATION_TIMEOUT_SECS",
+            DEFAULT_INVITATION_TIMEOUT_SECS,
+        );
+        let rate_limits = RateLimits {
+            session: KindLimits::from_env("SESSION", DEFAULT_SESSION_LIMITS),
+            user: KindLimits::from_env("USER", DEFAULT_USER_LIMITS),
+            strikes: limit_from_env("WEBSOCKET_RATE_LIMIT_STRIKES", DEFAULT_STRIKES),
+        };
+        Config {
+            idle_timeout,
+            ping_interval,
+            away_after,
+            handshake_timeout,
+            resume_grace_period,
+            resume_buffer_size,
+            outbound_queue_size,
+            json_encoding,
+            shutdown_timeout,
+            max_frame_size,
+            invitation_timeout,
+            rate_limits,
+        }
+    }
+}
+
+fn secs_from_env(key: &str, default: u64) -> Duration {
+    Duration::from_secs(number_from_env(key, default))
+}
+
+fn positive_secs_from_env(key: &str, default: u64) -> Duration {
+    let secs = secs_from_env(key, default);
+    if secs.is_zero() {
+        panic!("{key} needs to be positive!");
+    }
+    secs
+}
+
+fn number_from_env<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .map(|number| {
+            number
+                .parse()
+                .unwrap_or_else(|_| panic!("{key} needs to be a number!"))
+        })
+        .unwrap_or(default)
+}
+
+/// Parses limits in the form `<burst>/<per minute>`
+fn limit_from_env(key: &str, default: Limit) -> Limit {
+    let Ok(limit) = std::env::var(key) else {
+        return default;
+    };
+    let parsed = limit
+        .split_once('/')
+        .and_then(|(burst, per_minute)| Some((burst.parse().ok()?, per_minute.parse().ok()?)));
+    match parsed {
+        Some((burst, per_minute)) if per_minute > 0 => Limit::new(burst, per_minute),
+        _ => panic!("{key} needs to be of the form <burst>/<per minute>!"),
+    }
+}
@@ -0,0 +1,121 @@
+use actix_ws::Session;
+use p2pcv_protobuf::{client_to_server, server_to_client};
+use prost::Message;
+
+use super::WebsocketError;
+
+/// How the messages of a connection are encoded. The frame type of the
+/// `Hello` decides it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Protobuf in binary frames
+    Protobuf,
+    /// The JSON mapping of the messages in text frames. Meant for debugging.
+    Json,
+}
+
+impl Encoding {
+    /// The encoding and the content of a frame, that carries a message. Text
+    /// frames only do so, if the JSON encoding is enabled.
+    pub fn of_frame(msg: &actix_ws::Message, json_enabled: bool) -> Option<(Self, &[u8])> {
+        match msg {
+            actix_ws::Message::Binary(bytes) => Some((Encoding::Protobuf, bytes.as_ref())),
+            actix_ws::Message::Text(text) if json_enabled => {
+                Some((Encoding::Json, text.as_bytes()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn decode(self, frame: &[u8]) -> Result<client_to_server::Msg, WebsocketError> {
+        let msg = match self {
+            Encoding::Protobuf => client_to_server::Msg::decode(frame)?,
+            Encoding::Json => serde_json::from_slice(frame)?,
+        };
+        Ok(msg)
+    }
+
+    pub async fn send(
+        self,
+        session: &mut Session,
+        msg: server_to_client::Msg,
+    ) -> Result<(), WebsocketError> {
+        match self {
+            Encoding::Protobuf => {
+                let mut buf = Vec::new();
+                msg.encode(&mut buf)?;
+                session.binary(buf).await?;
+            }
+            Encoding::Json => {
+                let text = serde_json::to_string(&msg)?;
+                session.text(text).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use p2pcv_protobuf::{
+        client_to_server::{msg::C2s, new_game_event_response, NewGameEventResponse},
+        server_to_client::{msg::S2c, new_game_response, NewGameResponse},
+    };
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn client_message_round_trips_through_json() {
+        let sender_id = Uuid::new_v4();
+        let msg = client_to_server::Msg {
+            id: 7,
+            c2s: Some(C2s::NewGameEventResponse(NewGameEventResponse {
+                answer: new_game_event_response::Answer::Decline as i32,
+                peer_id: Some(vec![1, 2, 3]),
+                sender_user_id: sender_id.as_bytes().to_vec(),
+                request_id: 3,
+                supported_versions: vec!["1.0.0".to_owned()],
+            })),
+        };
+        let frame = serde_json::to_vec(&msg).unwrap();
+
+        // The oneof is flattened, bytes are base64 and enums are numbers
+        let json: serde_json::Value = serde_json::from_slice(&frame).unwrap();
+        let response = &json["newGameEventResponse"];
+        assert_eq!(response["senderUserId"], STANDARD.encode(sender_id));
+        assert_eq!(response["peerId"], STANDARD.encode([1, 2, 3]));
+        assert_eq!(response["answer"], 1);
+
+        assert_eq!(Encoding::Json.decode(&frame).unwrap(), msg);
+    }
+
+    #[test]
+    fn server_message_round_trips_through_json() {
+        let invitation_id = Uuid::new_v4();
+        let game_id = Uuid::new_v4();
+        let msg = server_to_client::Msg {
+            id: -1,
+            s2c: Some(S2c::NewGameResponse(NewGameResponse {
+                answer: Some(new_game_response::Answer::Accepted as i32),
+                peer_id: None,
+                error: None,
+                invitation_id: invitation_id.as_bytes().to_vec(),
+                variant_version: "1.2.0".to_owned(),
+                game_id: game_id.as_bytes().to_vec(),
+            })),
+            seq: 4,
+        };
+        let text = serde_json::to_string(&msg).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let response = &json["newGameResponse"];
+        assert_eq!(response["invitationId"], STANDARD.encode(invitation_id));
+        assert_eq!(response["gameId"], STANDARD.encode(game_id));
+        assert_eq!(response["answer"], 0);
+
+        let decoded: server_to_client::Msg = serde_json::from_str(&text).unwrap();
+        assert_eq!(decoded, msg);
+    }
+}
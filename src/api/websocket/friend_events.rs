@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use p2pcv_protobuf::server_to_client::{
+    self, friend_request_event, msg::S2c, FriendRemoved, FriendRequestEvent,
+};
+
+use crate::db::users::PublicUser;
+
+use super::{WebsocketError, Websockets};
+
+impl From<PublicUser> for server_to_client::PublicUser {
+    fn from(value: PublicUser) -> Self {
+        let PublicUser {
+            id,
+            user_name,
+            created_at,
+        } = value;
+        server_to_client::PublicUser {
+            id: id.as_bytes().to_vec(),
+            user_name,
+            created_at: created_at.timestamp_millis(),
+        }
+    }
+}
+
+/// A friend request, that changed
+pub struct FriendRequestChange {
+    pub kind: friend_request_event::Kind,
+    pub sender: PublicUser,
+    pub receiver: PublicUser,
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Pushes the change of a friend request to the sessions of both users
+pub async fn publish_friend_request(
+    ws_server: &Websockets,
+    change: FriendRequestChange,
+) -> Result<(), WebsocketError> {
+    let FriendRequestChange {
+        kind,
+        sender,
+        receiver,
+        message,
+        created_at,
+    } = change;
+    let event = |direction: friend_request_event::Direction, user: &PublicUser| {
+        S2c::FriendRequestEvent(FriendRequestEvent {
+            kind: kind as i32,
+            direction: direction as i32,
+            user: Some(user.clone().into()),
+            message: message.clone(),
+            created_at: created_at.timestamp_millis(),
+        })
+    };
+    let incoming = event(friend_request_event::Direction::Incoming, &sender);
+    let outgoing = event(friend_request_event::Direction::Outgoing, &receiver);
+    ws_server.push_to_user(receiver.id, incoming).await?;
+    ws_server.push_to_user(sender.id, outgoing).await
+}
+
+/// Pushes the end of the friendship to the sessions of both users
+pub async fn publish_friend_removed(
+    ws_server: &Websockets,
+    user: PublicUser,
+    friend: PublicUser,
+) -> Result<(), WebsocketError> {
+    let (user_id, friend_id) = (user.id, friend.id);
+    let removed = |other: PublicUser| {
+        S2c::FriendRemoved(FriendRemoved {
+            user: Some(other.into()),
+        })
+    };
+    ws_server.push_to_user(user_id, removed(friend)).await?;
+    ws_server.push_to_user(friend_id, removed(user)).await
+}
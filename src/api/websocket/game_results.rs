@@ -0,0 +1,143 @@
+use std::{collections::HashSet, sync::Arc};
+
+use p2pcv_protobuf::{
+    client_to_server::{report_game_result, ReportGameResult},
+    server_to_client::{game_result, msg::S2c, GameResult},
+};
+use uuid::Uuid;
+
+use crate::{
+    db::games::{Game, Outcome, PlayerResult, ReportStatus, Termination},
+    redis_db::websocket_sessions::WebsocketSessionEntry,
+};
+
+use super::{cluster::ClusterMessage, WebsocketError, WebsocketSession, Websockets};
+
+impl From<report_game_result::Result> for PlayerResult {
+    fn from(value: report_game_result::Result) -> Self {
+        match value {
+            report_game_result::Result::Win => PlayerResult::Win,
+            report_game_result::Result::Loss => PlayerResult::Loss,
+            report_game_result::Result::Draw => PlayerResult::Draw,
+            report_game_result::Result::Abort => PlayerResult::Abort,
+        }
+    }
+}
+
+impl From<report_game_result::Termination> for Termination {
+    fn from(value: report_game_result::Termination) -> Self {
+        use report_game_result::Termination as T;
+        match value {
+            T::Checkmate => Termination::Checkmate,
+            T::Resignation => Termination::Resignation,
+            T::Timeout => Termination::Timeout,
+            T::Stalemate => Termination::Stalemate,
+            T::Agreement => Termination::Agreement,
+            T::Repetition => Termination::Repetition,
+            T::InsufficientMaterial => Termination::InsufficientMaterial,
+            T::Abandonment => Termination::Abandonment,
+            T::Other => Termination::Other,
+        }
+    }
+}
+
+impl From<Outcome> for game_result::Outcome {
+    fn from(value: Outcome) -> Self {
+        match value {
+            Outcome::SenderWon => game_result::Outcome::SenderWon,
+            Outcome::ReceiverWon => game_result::Outcome::ReceiverWon,
+            Outcome::Draw => game_result::Outcome::Draw,
+            Outcome::Aborted => game_result::Outcome::Aborted,
+        }
+    }
+}
+
+impl From<ReportStatus> for game_result::Status {
+    fn from(value: ReportStatus) -> Self {
+        match value {
+            ReportStatus::PendingConfirmation => game_result::Status::PendingConfirmation,
+            ReportStatus::Finished => game_result::Status::Finished,
+            ReportStatus::Disputed => game_result::Status::Disputed,
+        }
+    }
+}
+
+/// What the players are told about a report of `reported_by`
+pub fn game_result(
+    game: &Game,
+    reported_by: Uuid,
+    result: PlayerResult,
+    termination: Termination,
+    status: ReportStatus,
+) -> GameResult {
+    let outcome = Outcome::of(result, reported_by == game.sender_id);
+    GameResult {
+        game_id: game.id.as_bytes().to_vec(),
+        status: game_result::Status::from(status) as i32,
+        reported_by_user_id: reported_by.as_bytes().to_vec(),
+        outcome: game_result::Outcome::from(outcome) as i32,
+        termination: termination.as_str().to_owned(),
+    }
+}
+
+pub async fn handle_report(
+    ws_server: &Arc<Websockets>,
+    ws_session: &WebsocketSession,
+    request_id: i32,
+    report: ReportGameResult,
+) -> Result<(), WebsocketError> {
+    let WebsocketSession { id, user_id, .. } = ws_session;
+    let game_id = Uuid::from_slice(&report.game_id)?;
+    let result = report_game_result::Result::try_from(report.result)?.into();
+    let termination = report_game_result::Termination::try_from(report.termination)?.into();
+
+    let mut db = ws_server.pool.get().await?;
+    let (game, status) =
+        Game::report_result(&mut db, game_id, *user_id, result, termination).await?;
+    drop(db);
+
+    let update = game_result(&game, *user_id, result, termination, status);
+    ws_session
+        .reply(request_id, S2c::GameResult(update.clone()))
+        .await?;
+    let except = ws_server.cluster.entry(*id);
+    if let Err(err) = publish(ws_server, &game, update, Some(except)).await {
+        log::error!("Session {id}: Could not publish result of {game_id}: {err}");
+    }
+    Ok(())
+}
+
+/// Tells all sessions of both players about the report, except `except`.
+/// The sessions stop signaling for the game, once it's over.
+pub async fn publish(
+    ws_server: &Websockets,
+    game: &Game,
+    update: GameResult,
+    except: Option<WebsocketSessionEntry>,
+) -> Result<(), WebsocketError> {
+    let over = update.status != game_result::Status::PendingConfirmation as i32;
+    if over {
+        ws_server.accepted_games.remove(game.id);
+    }
+    let mut instance_ids = HashSet::new();
+    for user_id in [game.sender_id, game.receiver_id] {
+        for entry in ws_server.sessions_of_user(user_id).await? {
+            instance_ids.insert(entry.instance_id);
+            if Some(entry) == except {
+                continue;
+            }
+            let s2c = S2c::GameResult(update.clone());
+            if let Err(err) = ws_server.send_to(entry, None, s2c).await {
+                log::debug!("Session {}: Could not push: {err}", entry.session_id);
+            }
+        }
+    }
+    if over {
+        instance_ids.remove(&ws_server.cluster.instance_id);
+        let message = ClusterMessage::GameOver { game_id: game.id };
+        for instance_id in instance_ids {
+            ws_server.cluster.publish(instance_id, &message).await?;
+        }
+    }
+    Ok(())
+}
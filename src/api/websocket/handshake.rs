@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use actix_ws::{CloseCode, CloseReason, MessageStream, Session};
+use futures::StreamExt;
+use p2pcv_protobuf::{
+    client_to_server::{msg::C2s, Hello, Msg},
+    server_to_client::{self, msg::S2c, HelloResponse},
+    PROTOCOL_VERSION,
+};
+
+use super::{encoding::Encoding, WebsocketError};
+
+/// Oldest protocol version, that the server still understands
+const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// What the server offers beyond the plain protocol
+const FEATURES: &[&str] = &[
+    "session-resumption",
+    "multi-device-invitations",
+    "friend-presence",
+];
+
+/// Waits for the `Hello` of the client and answers it. Other messages are
+/// refused in the meantime. Returns the encoding of the connection or the
+/// reason to close it with, if the handshake fails.
+pub(super) async fn await_hello(
+    session: &mut Session,
+    msg_stream: &mut MessageStream,
+    timeout: Duration,
+    json_enabled: bool,
+) -> Result<Encoding, Option<CloseReason>> {
+    let handshake = async {
+        while let Some(Ok(msg)) = msg_stream.next().await {
+            if let Some((encoding, frame)) = Encoding::of_frame(&msg, json_enabled) {
+                let (request_id, err) = match encoding.decode(frame) {
+                    Ok(Msg {
+                        c2s: Some(C2s::Hello(hello)),
+                        id,
+                    }) => {
+                        handle_hello(session, encoding, id, hello).await?;
+                        return Ok(encoding);
+                    }
+                    Ok(Msg { id, .. }) => (id, WebsocketError::HandshakeRequired),
+                    Err(err) => (0, err),
+                };
+                let error = server_to_client::Error {
+                    code: err.to_string(),
+                };
+                reply(session, encoding, request_id, S2c::Error(error)).await?;
+                continue;
+            }
+            match msg {
+                actix_ws::Message::Ping(bytes) => {
+                    session.pong(&bytes).await.map_err(|_| None)?;
+                }
+                actix_ws::Message::Pong(_) => {}
+                _ => return Err(None),
+            }
+        }
+        Err(None)
+    };
+    tokio::time::timeout(timeout, handshake)
+        .await
+        .unwrap_or_else(|_| {
+            Err(Some(CloseReason {
+                code: CloseCode::Policy,
+                description: Some("handshake-timeout".to_owned()),
+            }))
+        })
+}
+
+async fn handle_hello(
+    session: &mut Session,
+    encoding: Encoding,
+    request_id: i32,
+    hello: Hello,
+) -> Result<(), Option<CloseReason>> {
+    let Hello {
+        protocol_version,
+        client_build,
+        features,
+    } = hello;
+    log::debug!(
+        "Hello from client {client_build} (Protocol: {protocol_version}, Features: {features:?})"
+    );
+    if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+        return Err(Some(CloseReason {
+            code: CloseCode::Protocol,
+            description: Some("incompatible-protocol-version".to_owned()),
+        }));
+    }
+    let response = HelloResponse {
+        protocol_version: PROTOCOL_VERSION,
+        server_build: env!("CARGO_PKG_VERSION").to_owned(),
+        features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+    };
+    reply(session, encoding, request_id, S2c::HelloResponse(response)).await
+}
+
+/// Answers outside of the buffered messages, since there is no session yet
+async fn reply(
+    session: &mut Session,
+    encoding: Encoding,
+    request_id: i32,
+    s2c: S2c,
+) -> Result<(), Option<CloseReason>> {
+    let msg = server_to_client::Msg {
+        id: request_id,
+        s2c: Some(s2c),
+        seq: 0,
+    };
+    encoding.send(session, msg).await.map_err(|_| None)
+}
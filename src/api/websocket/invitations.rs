@@ -0,0 +1,121 @@
+use chrono::{DateTime, Duration, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use p2pcv_protobuf::{
+    client_to_server::NewGameEventResponse, server_to_client::new_game_event_response_error,
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::redis_db::websocket_sessions::WebsocketSessionEntry;
+
+/// How long finished invitations are remembered to reject late answers
+pub const FINISHED_RETENTION_MINUTES: i64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationKey {
+    pub sender_id: Uuid,
+    pub receiver_id: Uuid,
+    /// Id of the `NewGame` message of the sender
+    pub request_id: i32,
+}
+
+/// The answer of the receiver together with the session it was sent from
+#[derive(Debug)]
+pub struct InvitationAnswer {
+    pub session: WebsocketSessionEntry,
+    pub response: NewGameEventResponse,
+}
+
+#[derive(Debug)]
+struct PendingInvitation {
+    tx: oneshot::Sender<InvitationAnswer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnswerError {
+    Expired,
+    /// Another session of the receiver answered first
+    Answered,
+    NotFound,
+}
+
+impl From<AnswerError> for new_game_event_response_error::Error {
+    fn from(value: AnswerError) -> Self {
+        match value {
+            AnswerError::Expired => new_game_event_response_error::Error::Expired,
+            AnswerError::Answered => new_game_event_response_error::Error::AnsweredElsewhere,
+            AnswerError::NotFound => new_game_event_response_error::Error::NotFound,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PendingInvitations {
+    pending: DashMap<InvitationKey, PendingInvitation>,
+    /// Why invitations, that can't be answered anymore, were finished
+    finished: DashMap<InvitationKey, (DateTime<Utc>, AnswerError)>,
+}
+
+impl PendingInvitations {
+    /// Registers an invitation. The returned receiver resolves, when the
+    /// receiver of the invitation answers. Returns None, if an invitation
+    /// with the same key is pending, since the answers couldn't be told
+    /// apart.
+    pub fn insert(&self, key: InvitationKey) -> Option<oneshot::Receiver<InvitationAnswer>> {
+        let Entry::Vacant(entry) = self.pending.entry(key) else {
+            return None;
+        };
+        let (tx, rx) = oneshot::channel();
+        entry.insert(PendingInvitation { tx });
+        self.finished.remove(&key);
+        Some(rx)
+    }
+
+    /// Removes an invitation without remembering it, e.g. if it could not be
+    /// delivered.
+    pub fn remove(&self, key: &InvitationKey) {
+        self.pending.remove(key);
+    }
+
+    /// Forwards the answer to the waiting sender. Only the first answer is
+    /// accepted.
+    pub fn answer(&self, key: &InvitationKey, answer: InvitationAnswer) -> Result<(), AnswerError> {
+        let Some((_, PendingInvitation { tx })) = self.pending.remove(key) else {
+            return match self.finished.get(key) {
+                Some(finished) => Err(finished.1),
+                None => Err(AnswerError::NotFound),
+            };
+        };
+        // The sender stopped waiting in the meantime
+        tx.send(answer).map_err(|_| AnswerError::Expired)?;
+        self.finish(key, AnswerError::Answered);
+        Ok(())
+    }
+
+    /// Marks the invitation as expired. Returns false, if it was answered in
+    /// the meantime.
+    pub fn expire(&self, key: &InvitationKey) -> bool {
+        if self.pending.remove(key).is_none() {
+            return false;
+        }
+        self.finish(key, AnswerError::Expired);
+        true
+    }
+
+    /// Expires all pending invitations, so that their senders stop waiting
+    pub fn expire_all(&self) {
+        let keys: Vec<_> = self.pending.iter().map(|entry| *entry.key()).collect();
+        for key in keys {
+            self.expire(&key);
+        }
+    }
+
+    fn finish(&self, key: &InvitationKey, reason: AnswerError) {
+        let now = Utc::now();
+        let dropoff = now - Duration::minutes(FINISHED_RETENTION_MINUTES);
+        self.finished
+            .retain(|_, (finished_at, _)| *finished_at > dropoff);
+        self.finished.insert(*key, (now, reason));
+    }
+}
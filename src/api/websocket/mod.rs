@@ -1,108 +1,403 @@
 use std::{
-    borrow::BorrowMut,
-    collections::HashMap,
     sync::{
-        atomic::{AtomicIsize, AtomicU16, Ordering},
+        atomic::{AtomicI32, AtomicIsize, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 use actix_web::{
-    web::{self, Data, ServiceConfig},
-    HttpRequest, Responder,
+    http::{
+        header::{self, HeaderValue},
+        StatusCode,
+    },
+    web::{self, Data, Query, ServiceConfig},
+    HttpRequest, Responder, ResponseError,
 };
-use actix_ws::{CloseReason, Closed, Session};
+use actix_ws::{CloseCode, CloseReason, Closed, MessageStream, ProtocolError, Session};
 use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use p2pcv_protobuf::{
-    client_to_server::{
-        self,
-        msg::{self, C2s},
-        new_game_event_response, Msg, NewGame, NewGameEventResponse,
+    client_to_server::{msg::C2s, new_game_event_response, Msg, NewGame, NewGameEventResponse},
+    server_to_client::{
+        self, msg::S2c, new_game_event_cancelled, new_game_event_response_error, new_game_response,
+        NewGameAccepted, NewGameEvent, NewGameEventCancelled, NewGameEventResponseError,
+        NewGameResponse, RateLimited, SessionStarted,
     },
-    server_to_client::{msg::S2c, NewGameResponse},
 };
 use prost::Message;
 use thiserror::Error;
-use tokio::sync::Mutex;
 use uuid::Uuid;
 
-use crate::{api::auth::session::auth::Auth, error::AppError};
+use crate::{
+    api::auth::session::ticket::WebsocketAuth,
+    db::{
+        db_conn::DbPool,
+        games::{GameInvitation, InvitationStatus, NewGameInvitation, TimeControl},
+        users::User,
+        variants::VariantVersion,
+    },
+    error::AppError,
+    redis_db::websocket_sessions::WebsocketSessionEntry,
+};
 use std::fmt::Debug;
 
+use self::{
+    cluster::{Cluster, ClusterMessage},
+    encoding::Encoding,
+    invitations::{
+        AnswerError, InvitationAnswer, InvitationKey, PendingInvitations,
+        FINISHED_RETENTION_MINUTES,
+    },
+    outbox::{Outbox, QueueMetrics},
+    rate_limit::{RateLimiter, RequestKind, SessionRateLimiter},
+    shutdown::Shutdown,
+    signaling::{AcceptedGame, AcceptedGames, Signal},
+};
+
+pub mod admin;
+pub mod chat;
+pub mod cluster;
+pub mod config;
+pub mod encoding;
+pub mod friend_events;
+pub mod game_results;
+pub mod handshake;
+pub mod invitations;
+pub mod negotiation;
+pub mod outbox;
+pub mod presence;
+pub mod rate_limit;
+pub mod reaper;
+pub mod shutdown;
+pub mod signaling;
+
+pub type Config = config::Config;
+
 pub fn config(cfg: &mut ServiceConfig) {
-    cfg.service(ws).app_data(Data::new(Websockets::default()));
+    cfg.service(ws);
+}
+
+/// Lets a client resume its session after the connection dropped
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResumeQuery {
+    resume_token: Uuid,
+    /// Seq of the last message, the client received
+    last_seq: u64,
 }
 
 #[get("ws")]
 async fn ws(
     ws_server: Data<Websockets>,
-    auth: Auth,
+    auth: WebsocketAuth,
     req: HttpRequest,
     body: web::Payload,
 ) -> actix_web::Result<impl Responder> {
-    let (response, session, mut msg_stream) = actix_ws::handle(&req, body)?;
+    if ws_server.shutdown.is_draining() {
+        return Err(AppError::ShuttingDown.into());
+    }
+    let (mut response, session, msg_stream) = actix_ws::handle(&req, body)?;
+    // Browsers drop the connection, unless the offered protocol is selected
+    if let Some(protocol) = auth.protocol {
+        let value = HeaderValue::from_str(&protocol).map_err(|_| AppError::Unauthorized)?;
+        response
+            .headers_mut()
+            .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+    }
+    let resume = Query::<ResumeQuery>::from_query(req.query_string())
+        .ok()
+        .map(Query::into_inner);
     let ws_server = ws_server.into_inner();
-    let user_id = auth.user_id;
-    let id = Uuid::new_v4();
-    let now = Utc::now();
-    let ws_session = WebsocketSession {
-        id,
+    let msg_stream = msg_stream.max_frame_size(ws_server.config.max_frame_size);
+    actix_web::rt::spawn(run_connection(
+        ws_server,
+        auth.user_id,
+        resume,
         session,
-        user_id,
-        last_pinged: Arc::new(AtomicIsize::new(now.timestamp() as isize)),
+        msg_stream,
+    ));
+
+    Ok(response)
+}
+
+/// Serves a connection from the handshake until it's closed
+async fn run_connection(
+    ws_server: Arc<Websockets>,
+    user_id: Uuid,
+    resume: Option<ResumeQuery>,
+    mut session: Session,
+    mut msg_stream: MessageStream,
+) {
+    let Config {
+        handshake_timeout,
+        json_encoding,
+        ..
+    } = ws_server.config;
+    let handshake = handshake::await_hello(
+        &mut session,
+        &mut msg_stream,
+        handshake_timeout,
+        json_encoding,
+    );
+    let encoding = match handshake.await {
+        Ok(encoding) => encoding,
+        Err(reason) => {
+            log::info!("Handshake with {user_id} failed: {reason:?}");
+            session.close(reason).await.ok();
+            return;
+        }
     };
-    let session2 = ws_session.clone();
-    ws_server.sessions.insert(id, session2);
+    let started = start_session(&ws_server, user_id, resume, &session, encoding).await;
+    let (ws_session, generation) = match started {
+        Ok(started) => started,
+        Err(err) => {
+            log::error!("Could not start session of {user_id}: {err}");
+            session.close(None).await.ok();
+            return;
+        }
+    };
+    let id = ws_session.id;
+    if let Err(err) = presence::publish(&ws_server, user_id).await {
+        log::error!("Could not publish presence of {user_id}: {err}");
+    }
 
-    actix_web::rt::spawn(async move {
-        let mut session = ws_session.session.clone();
-        while let Some(Ok(msg)) = msg_stream.next().await {
-            if handle_client_message(&ws_server, &ws_session, &mut session, msg)
-                .await
-                .is_err()
-            {
+    // Set to the reason to close with, if the session can't be resumed
+    let mut close = None;
+    while let Some(msg) = msg_stream.next().await {
+        let result = match msg {
+            Ok(msg) => {
+                handle_client_message(&ws_server, &ws_session, &mut session, encoding, msg).await
+            }
+            Err(ProtocolError::Overflow) => Err(WebsocketError::FrameTooLarge),
+            Err(_) => break,
+        };
+        if let Err(err) = result {
+            if err.closes_session() {
+                // Only a dropped connection leaves the session resumable
+                if !matches!(err, WebsocketError::WebsocketClosed(_)) {
+                    log::info!("Session {id}: {err} - Closing (User Id: {user_id})");
+                    close = Some(err.close_reason());
+                }
                 break;
-            };
+            }
+            log::error!("Session {id}: {err} (User Id: {user_id})");
+        };
+    }
+
+    if ws_session.outbox.lock().await.generation() != generation {
+        // Resumed on another connection in the meantime
+        return;
+    }
+    match close {
+        Some(reason) => {
+            close_session(&ws_server, ws_session, reason).await.ok();
         }
+        None => detach_session(&ws_server, ws_session, generation, None).await,
+    }
+}
 
-        close_session(&ws_server, ws_session).await.ok();
+/// Resumes the session, the client asked for, or starts a new one. Returns
+/// the session and the generation of its connection.
+async fn start_session(
+    ws_server: &Arc<Websockets>,
+    user_id: Uuid,
+    resume: Option<ResumeQuery>,
+    session: &Session,
+    encoding: Encoding,
+) -> Result<(WebsocketSession, u64), WebsocketError> {
+    let started = SessionStarted {
+        resume_token: String::new(),
+        resumed: false,
+        messages_lost: false,
+        resume_grace_secs: ws_server.config.resume_grace_period.as_secs() as u32,
+    };
+    let resumable = resume.and_then(|resume| {
+        let ResumeQuery {
+            resume_token,
+            last_seq,
+        } = resume;
+        let ws_session = ws_server.resumable_session(user_id, resume_token)?;
+        Some((ws_session, last_seq))
     });
-
-    Ok(response)
+    let (ws_session, generation) = match resumable {
+        Some((ws_session, last_seq)) => {
+            let started = SessionStarted {
+                resume_token: ws_session.resume_token.to_string(),
+                ..started
+            };
+            let mut outbox = ws_session.outbox.lock().await;
+            let (generation, replaced) =
+                outbox.attach(session.clone(), encoding, last_seq, started);
+            drop(outbox);
+            if let Some(replaced) = replaced {
+                replaced.close(None).await.ok();
+            }
+            ws_session.update_pinged();
+            log::info!("Session {}: Resumed (User Id: {user_id})", ws_session.id);
+            (ws_session, generation)
+        }
+        None => {
+            let resume_token = Uuid::new_v4();
+            let started = SessionStarted {
+                resume_token: resume_token.to_string(),
+                ..started
+            };
+            let msg = server_to_client::Msg {
+                id: 0,
+                s2c: Some(S2c::SessionStarted(started)),
+                seq: 0,
+            };
+            encoding.send(&mut session.clone(), msg).await?;
+            let outbox = Outbox::new(
+                session.clone(),
+                encoding,
+                ws_server.config.resume_buffer_size,
+                ws_server.config.outbound_queue_size,
+                ws_server.queue_metrics.clone(),
+            );
+            let ws_session = WebsocketSession {
+                id: Uuid::new_v4(),
+                user_id,
+                resume_token,
+                last_pinged: Arc::new(AtomicIsize::new(Utc::now().timestamp() as isize)),
+                last_push_id: Arc::new(AtomicI32::new(0)),
+                connected_at: Utc::now(),
+                rate_limiter: Arc::new(std::sync::Mutex::new(SessionRateLimiter::new(
+                    ws_server.config.rate_limits.strikes,
+                ))),
+                outbox: Arc::new(tokio::sync::Mutex::new(outbox)),
+            };
+            ws_server.sessions.insert(ws_session.id, ws_session.clone());
+            (ws_session, 0)
+        }
+    };
+    ws_server
+        .cluster
+        .register(ws_session.id, user_id, Utc::now())
+        .await?;
+    Ok((ws_session, generation))
 }
 
 async fn close_session(
     ws_server: &Arc<Websockets>,
     session: WebsocketSession,
+    reason: Option<CloseReason>,
 ) -> Result<(), Closed> {
     ws_server.sessions.remove(&session.id);
-    let WebsocketSession { session, .. } = session;
-    session.close(None).await
+    if !ws_server
+        .sessions
+        .iter()
+        .any(|entry| entry.user_id == session.user_id)
+    {
+        ws_server.user_rate_limiters.remove(&session.user_id);
+    }
+    let entry = ws_server.cluster.entry(session.id);
+    ws_server.accepted_games.remove_session(entry);
+    if let Err(err) = ws_server
+        .cluster
+        .unregister(session.id, session.user_id)
+        .await
+    {
+        log::error!("Session {}: Could not unregister: {err}", session.id);
+    }
+    if let Err(err) = presence::publish(ws_server, session.user_id).await {
+        log::error!("Could not publish presence of {}: {err}", session.user_id);
+    }
+    let Some(closing) = session.outbox.lock().await.close() else {
+        return Ok(());
+    };
+    closing.close(reason).await
+}
+
+/// Keeps the session for the grace period after its connection dropped, so
+/// that the client can resume it. Closes the session afterwards.
+async fn detach_session(
+    ws_server: &Arc<Websockets>,
+    ws_session: WebsocketSession,
+    generation: u64,
+    reason: Option<CloseReason>,
+) {
+    let Some(session) = ws_session.outbox.lock().await.detach(generation) else {
+        return;
+    };
+    session.close(reason).await.ok();
+    let WebsocketSession { id, user_id, .. } = ws_session;
+    log::info!("Session {id}: Detached (User Id: {user_id})");
+
+    let ws_server = ws_server.clone();
+    actix_web::rt::spawn(async move {
+        let grace_period = ws_server.config.resume_grace_period;
+        tokio::time::sleep(grace_period).await;
+        let grace_period = chrono::Duration::seconds(grace_period.as_secs() as i64);
+        if !ws_session.outbox.lock().await.detached_for(grace_period) {
+            return;
+        }
+        log::info!("Session {id}: Not resumed in time - Closing (User Id: {user_id})");
+        close_session(&ws_server, ws_session, None).await.ok();
+    });
 }
 
 async fn handle_client_message(
     ws_server: &Arc<Websockets>,
     ws_session: &WebsocketSession,
     session: &mut Session,
+    encoding: Encoding,
     msg: actix_ws::Message,
 ) -> Result<(), WebsocketError> {
     let WebsocketSession { id, user_id, .. } = ws_session;
+    if let Some((frame_encoding, frame)) = Encoding::of_frame(&msg, ws_server.config.json_encoding)
+    {
+        ws_session.update_pinged();
+        // Messages, that can't be handled, count against the limits too
+        let invalid =
+            |err| rate_limit::check(ws_server, ws_session, RequestKind::Invalid).and(Err(err));
+        let (request_id, result) = match frame_encoding.decode(frame) {
+            Ok(Msg { id, .. }) if frame_encoding != encoding => {
+                (id, invalid(WebsocketError::EncodingMismatch))
+            }
+            Ok(Msg {
+                c2s: Some(request),
+                id,
+            }) => (id, handle_c2s(ws_server, ws_session, id, request).await),
+            Ok(Msg { c2s: None, id }) => (id, invalid(WebsocketError::ClientEmptyRequest)),
+            Err(err) => (0, invalid(err)),
+        };
+        if let Err(err) = result {
+            if err.closes_session() {
+                return Err(err);
+            }
+            log::debug!("Session {id}: Request {request_id} failed: {err} (User Id: {user_id})");
+            let error = match err {
+                WebsocketError::RateLimited(retry_after) => S2c::RateLimited(RateLimited {
+                    retry_after_ms: retry_after.as_millis() as u32,
+                }),
+                err => S2c::Error(server_to_client::Error {
+                    code: err.to_string(),
+                }),
+            };
+            if frame_encoding == encoding {
+                ws_session.reply(request_id, error).await?;
+            } else {
+                // Answered with the frame type of the request, so that the
+                // client can read it
+                let msg = server_to_client::Msg {
+                    id: request_id,
+                    s2c: Some(error),
+                    seq: 0,
+                };
+                frame_encoding.send(session, msg).await?;
+            }
+        }
+        return Ok(());
+    }
     match msg {
         actix_ws::Message::Ping(bytes) => {
             ws_session.update_pinged();
             session.pong(&bytes).await?;
         }
-        actix_ws::Message::Binary(msg) => {
+        actix_ws::Message::Pong(_) => {
             ws_session.update_pinged();
-            let Msg {
-                c2s: Some(request),
-                id,
-            } = client_to_server::Msg::decode(msg)?
-            else {
-                return Err(WebsocketError::ClientEmptyRequest);
-            };
-            handle_c2s(ws_server, ws_session, session, request).await?;
         }
         actix_ws::Message::Close(reason) => {
             log::info!("Session {id}: Closed by client (User Id: {user_id})");
@@ -125,81 +420,721 @@ async fn handle_client_message(
 async fn handle_c2s(
     ws_server: &Arc<Websockets>,
     ws_session: &WebsocketSession,
-    session: &mut Session,
+    request_id: i32,
     request: C2s,
 ) -> Result<(), WebsocketError> {
+    rate_limit::check(ws_server, ws_session, RequestKind::from(&request))?;
     match request {
-        C2s::NewGame(new_game) => handle_new_game(ws_server, ws_session, session, new_game).await?,
+        C2s::NewGame(new_game) => {
+            handle_new_game(ws_server, ws_session, request_id, new_game).await?
+        }
         C2s::NewGameEventResponse(response) => {
-            handle_new_game_event_response(ws_server, ws_session, session, response).await?
+            handle_new_game_event_response(ws_server, ws_session, request_id, response).await?
+        }
+        C2s::SdpOffer(offer) => {
+            let signal = Signal::SdpOffer(offer);
+            signaling::handle_signal(ws_server, ws_session, request_id, signal).await?
+        }
+        C2s::SdpAnswer(answer) => {
+            let signal = Signal::SdpAnswer(answer);
+            signaling::handle_signal(ws_server, ws_session, request_id, signal).await?
+        }
+        C2s::IceCandidate(candidate) => {
+            let signal = Signal::IceCandidate(candidate);
+            signaling::handle_signal(ws_server, ws_session, request_id, signal).await?
+        }
+        C2s::SendChatMessage(send) => {
+            chat::handle_send(ws_server, ws_session, request_id, send).await?
+        }
+        C2s::ReportGameResult(report) => {
+            game_results::handle_report(ws_server, ws_session, request_id, report).await?
         }
+        C2s::Hello(_) => return Err(WebsocketError::HandshakeDone),
     }
     Ok(())
 }
 
-async fn send_response(session: &mut Session, response: S2c) -> Result<(), WebsocketError> {
-    let mut buf = Vec::new();
-    response.encode(&mut buf);
-    session.binary(buf).await?;
-    Ok(())
+fn new_game_error(error: new_game_response::Error) -> NewGameResponse {
+    NewGameResponse {
+        answer: None,
+        peer_id: None,
+        error: Some(error as i32),
+        invitation_id: Vec::new(),
+        variant_version: String::new(),
+        game_id: Vec::new(),
+    }
+}
+
+async fn send_new_game_error(
+    ws_session: &WebsocketSession,
+    request_id: i32,
+    error: new_game_response::Error,
+) -> Result<(), WebsocketError> {
+    ws_session
+        .reply(request_id, S2c::NewGameResponse(new_game_error(error)))
+        .await
+}
+
+impl From<TimeControl> for server_to_client::TimeControl {
+    fn from(value: TimeControl) -> Self {
+        server_to_client::TimeControl {
+            initial_secs: value.initial_secs,
+            increment_secs: value.increment_secs,
+        }
+    }
 }
 
 async fn handle_new_game(
     ws_server: &Arc<Websockets>,
     ws_session: &WebsocketSession,
-    session: &mut Session,
+    request_id: i32,
     new_game: NewGame,
 ) -> Result<(), WebsocketError> {
-    let response = NewGameResponse {
-        error: None,
-        answer: Some(new_game_event_response::Answer::Accept as i32),
-        // Debug
-        peer_id: Some(Uuid::new_v4().as_bytes().to_vec()),
-    };
-    let res = S2c::NewGameResponse(response);
-    send_response(session, res).await?;
+    let NewGame {
+        receiver_user_id,
+        variant_id,
+        variant_version,
+        time_control,
+        supported_versions,
+    } = new_game;
+    let time_control = time_control.map(|time_control| TimeControl {
+        initial_secs: time_control.initial_secs,
+        increment_secs: time_control.increment_secs,
+    });
+    if time_control.is_some_and(|time_control| !time_control.is_valid()) {
+        return Err(WebsocketError::InvalidTimeControl);
+    }
+    let invitation = NewGameInvitation {
+        sender_id: ws_session.user_id,
+        receiver_id: Uuid::from_slice(&receiver_user_id)?,
+        variant_id: Uuid::from_slice(&variant_id)?,
+        variant_version,
+        initial_secs: time_control.map(|time_control| time_control.initial_secs),
+        increment_secs: time_control.map(|time_control| time_control.increment_secs),
+    };
+    let inviter = Inviter::Session {
+        ws_session: ws_session.clone(),
+        request_id,
+    };
+    if let Err(error) = invite(ws_server, inviter, invitation, supported_versions).await? {
+        return send_new_game_error(ws_session, request_id, error.into()).await;
+    }
     Ok(())
 }
 
+/// Who waits for the answer to an invitation
+#[derive(Clone)]
+pub enum Inviter {
+    /// The session, that sent the `NewGame` request
+    Session {
+        ws_session: WebsocketSession,
+        request_id: i32,
+    },
+    /// The invitation was sent over the REST api. The answer is pushed to all
+    /// sessions of the sender.
+    Rest,
+}
+
+impl Inviter {
+    /// REST invitations don't answer a client message, so they get a
+    /// negative request id, that is derived from the invitation id
+    fn request_id(&self, invitation_id: Uuid) -> i32 {
+        match self {
+            Inviter::Session { request_id, .. } => *request_id,
+            Inviter::Rest => {
+                let [a, b, c, d, ..] = *invitation_id.as_bytes();
+                i32::from_be_bytes([a, b, c, d]) | i32::MIN
+            }
+        }
+    }
+
+    async fn respond(
+        &self,
+        ws_server: &Websockets,
+        sender_id: Uuid,
+        response: NewGameResponse,
+    ) -> Result<(), WebsocketError> {
+        let s2c = S2c::NewGameResponse(response);
+        match self {
+            Inviter::Session {
+                ws_session,
+                request_id,
+            } => ws_session.reply(*request_id, s2c).await,
+            Inviter::Rest => ws_server.push_to_user(sender_id, s2c).await,
+        }
+    }
+
+    /// The session, that exchanges the WebRTC handshake for the sender
+    async fn session(
+        &self,
+        ws_server: &Websockets,
+        sender_id: Uuid,
+    ) -> Result<Option<WebsocketSessionEntry>, WebsocketError> {
+        match self {
+            Inviter::Session { ws_session, .. } => Ok(Some(ws_server.cluster.entry(ws_session.id))),
+            Inviter::Rest => Ok(ws_server
+                .sessions_of_user(sender_id)
+                .await?
+                .first()
+                .copied()),
+        }
+    }
+}
+
+/// Why an invitation is refused right away. Everything else is only known,
+/// once the receiver answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InviteError {
+    NotFriends,
+    ReceiverOffline,
+    ServerShutdown,
+    UnknownVariant,
+    VariantWithdrawn,
+    DuplicateRequest,
+}
+
+impl From<InviteError> for new_game_response::Error {
+    fn from(value: InviteError) -> Self {
+        match value {
+            InviteError::NotFriends => new_game_response::Error::NotFriends,
+            InviteError::ReceiverOffline => new_game_response::Error::ReceiverOffline,
+            InviteError::ServerShutdown => new_game_response::Error::ServerShutdown,
+            InviteError::UnknownVariant => new_game_response::Error::UnknownVariant,
+            InviteError::VariantWithdrawn => new_game_response::Error::VariantWithdrawn,
+            InviteError::DuplicateRequest => new_game_response::Error::DuplicateRequest,
+        }
+    }
+}
+
+/// Stores the invitation, rings on every session of the receiver and waits
+/// for the answer in the background. Returns the id of the invitation or why
+/// it was refused.
+pub async fn invite(
+    ws_server: &Arc<Websockets>,
+    inviter: Inviter,
+    new_invitation: NewGameInvitation,
+    sender_versions: Vec<String>,
+) -> Result<Result<Uuid, InviteError>, WebsocketError> {
+    let NewGameInvitation {
+        sender_id,
+        receiver_id,
+        ..
+    } = new_invitation;
+    let mut db = ws_server.pool.get().await?;
+    if !User::is_friends_with(&mut db, sender_id, receiver_id).await? {
+        return Ok(Err(InviteError::NotFriends));
+    }
+    let variant_version = VariantVersion::get(
+        &mut db,
+        new_invitation.variant_id,
+        &new_invitation.variant_version,
+    )
+    .await?;
+    match variant_version {
+        None => return Ok(Err(InviteError::UnknownVariant)),
+        Some(VariantVersion {
+            withdrawn_at: Some(_),
+            ..
+        }) => return Ok(Err(InviteError::VariantWithdrawn)),
+        Some(_) => {}
+    }
+    let receiver_sessions = ws_server.sessions_of_user(receiver_id).await?;
+    if receiver_sessions.is_empty() {
+        return Ok(Err(InviteError::ReceiverOffline));
+    }
+    let sender = User::get(&mut db, sender_id).await?;
+    // Keeps a shutdown waiting, until the invitation has an outcome
+    let Some(shutdown_guard) = ws_server.shutdown.guard() else {
+        return Ok(Err(InviteError::ServerShutdown));
+    };
+    let invitation = GameInvitation::insert(&mut db, new_invitation).await?;
+    drop(db);
+    let invitation_id = invitation.id;
+    let request_id = inviter.request_id(invitation_id);
+
+    let key = InvitationKey {
+        sender_id,
+        receiver_id,
+        request_id,
+    };
+    let Some(mut rx) = ws_server.pending_invitations.insert(key) else {
+        resolve_invitation(ws_server, invitation_id, InvitationStatus::Cancelled).await;
+        return Ok(Err(InviteError::DuplicateRequest));
+    };
+    // Answers on other instances are routed here. Late answers still find
+    // this instance, which knows why the invitation is finished.
+    let timeout = ws_server.config.invitation_timeout;
+    let ttl = chrono::Duration::seconds(timeout.as_secs() as i64)
+        + chrono::Duration::minutes(FINISHED_RETENTION_MINUTES);
+    if let Err(err) = ws_server.cluster.register_invitation(&key, ttl).await {
+        ws_server.pending_invitations.remove(&key);
+        resolve_invitation(ws_server, invitation_id, InvitationStatus::Cancelled).await;
+        return Err(err);
+    }
+
+    let event = NewGameEvent {
+        sender_user_id: sender_id.as_bytes().to_vec(),
+        sender_user_name: sender.user_name,
+        variant_id: invitation.variant_id.as_bytes().to_vec(),
+        variant_version: invitation.variant_version.clone(),
+        timeout_secs: i32::try_from(ws_server.config.invitation_timeout.as_secs())
+            .unwrap_or(i32::MAX),
+        request_id,
+        invitation_id: invitation_id.as_bytes().to_vec(),
+        time_control: invitation.time_control().map(Into::into),
+    };
+    // Ring on every device of the receiver
+    let mut delivered = Vec::with_capacity(receiver_sessions.len());
+    for receiver_session in receiver_sessions {
+        let s2c = S2c::NewGameEvent(event.clone());
+        match ws_server.send_to(receiver_session, None, s2c).await {
+            Ok(()) => delivered.push(receiver_session),
+            Err(err) => log::debug!("Could not deliver invitation to {receiver_id}: {err}"),
+        }
+    }
+    if delivered.is_empty() {
+        ws_server.pending_invitations.remove(&key);
+        if let Err(err) = ws_server.cluster.unregister_invitation(&key).await {
+            log::error!("Could not unregister invitation {key:?}: {err}");
+        }
+        resolve_invitation(ws_server, invitation_id, InvitationStatus::Cancelled).await;
+        return Ok(Err(InviteError::ReceiverOffline));
+    }
+
+    // Wait for the answer of the receiver without blocking the reader of the
+    // sender's session.
+    let ws_server = ws_server.clone();
+    actix_web::rt::spawn(async move {
+        let _shutdown_guard = shutdown_guard;
+        let respond = |response: NewGameResponse| {
+            let response = NewGameResponse {
+                invitation_id: invitation_id.as_bytes().to_vec(),
+                ..response
+            };
+            let inviter = inviter.clone();
+            let ws_server = ws_server.clone();
+            async move {
+                if let Err(err) = inviter.respond(&ws_server, sender_id, response).await {
+                    log::debug!("Could not deliver answer of {receiver_id}: {err}");
+                }
+            }
+        };
+        let result = match tokio::time::timeout(timeout, &mut rx).await {
+            Ok(result) => result,
+            // Answered right before the timeout
+            Err(_) if !ws_server.pending_invitations.expire(&key) => rx.await,
+            Err(_) => {
+                log::debug!("Invitation {key:?} timed out");
+                resolve_invitation(&ws_server, invitation_id, InvitationStatus::TimedOut).await;
+                respond(new_game_error(new_game_response::Error::Timeout)).await;
+                let reason = new_game_event_cancelled::Reason::Timeout;
+                cancel_invitation(&ws_server, &key, delivered, reason).await;
+                return;
+            }
+        };
+        let Ok(InvitationAnswer {
+            session: receiver_session,
+            response:
+                NewGameEventResponse {
+                    answer,
+                    peer_id,
+                    supported_versions: receiver_versions,
+                    ..
+                },
+        }) = result
+        else {
+            // Pending invitations are only dropped, when the server shuts down
+            let (error, reason) = if ws_server.shutdown.is_draining() {
+                (
+                    new_game_response::Error::ServerShutdown,
+                    new_game_event_cancelled::Reason::ServerShutdown,
+                )
+            } else {
+                log::error!("Invitation {key:?} was dropped unexpectedly");
+                (
+                    new_game_response::Error::ServerError,
+                    new_game_event_cancelled::Reason::ServerError,
+                )
+            };
+            resolve_invitation(&ws_server, invitation_id, InvitationStatus::Cancelled).await;
+            respond(new_game_error(error)).await;
+            cancel_invitation(&ws_server, &key, delivered, reason).await;
+            return;
+        };
+        // Close the invitation on the devices, that didn't answer
+        let others = delivered
+            .into_iter()
+            .filter(|session| *session != receiver_session);
+        let reason = new_game_event_cancelled::Reason::AnsweredElsewhere;
+        cancel_invitation(&ws_server, &key, others, reason).await;
+        let answer = match new_game_event_response::Answer::try_from(answer) {
+            Ok(new_game_event_response::Answer::Accept) => new_game_response::Answer::Accepted,
+            Ok(new_game_event_response::Answer::Decline) | Err(_) => {
+                new_game_response::Answer::Declined
+            }
+        };
+        let (peer_id, variant_version, game_id) = match answer {
+            new_game_response::Answer::Accepted => {
+                let negotiated = negotiation::negotiate(
+                    &ws_server,
+                    &invitation,
+                    &sender_versions,
+                    &receiver_versions,
+                )
+                .await;
+                let variant_version = match negotiated {
+                    Ok(Some(variant_version)) => variant_version,
+                    Ok(None) => {
+                        log::debug!("Invitation {key:?}: No common version of the variant");
+                        let status = InvitationStatus::Incompatible;
+                        resolve_invitation(&ws_server, invitation_id, status).await;
+                        respond(new_game_error(
+                            new_game_response::Error::IncompatibleVariant,
+                        ))
+                        .await;
+                        let reason = new_game_event_cancelled::Reason::IncompatibleVariant;
+                        cancel_invitation(&ws_server, &key, [receiver_session], reason).await;
+                        return;
+                    }
+                    Err(err) => {
+                        log::error!("Invitation {key:?}: Could not negotiate the version: {err}");
+                        let status = InvitationStatus::Cancelled;
+                        resolve_invitation(&ws_server, invitation_id, status).await;
+                        respond(new_game_error(new_game_response::Error::ServerError)).await;
+                        let reason = new_game_event_cancelled::Reason::ServerError;
+                        cancel_invitation(&ws_server, &key, [receiver_session], reason).await;
+                        return;
+                    }
+                };
+                let peer_id = peer_id.unwrap_or_else(|| Uuid::new_v4().as_bytes().to_vec());
+                let version = variant_version.clone();
+                let Some(game_id) =
+                    start_game(&ws_server, invitation_id, peer_id.clone(), version).await
+                else {
+                    // Still pending, if the game wasn't stored
+                    let status = InvitationStatus::Cancelled;
+                    resolve_invitation(&ws_server, invitation_id, status).await;
+                    respond(new_game_error(new_game_response::Error::ServerError)).await;
+                    let reason = new_game_event_cancelled::Reason::ServerError;
+                    cancel_invitation(&ws_server, &key, [receiver_session], reason).await;
+                    return;
+                };
+                let accepted = NewGameAccepted {
+                    sender_user_id: sender_id.as_bytes().to_vec(),
+                    request_id,
+                    invitation_id: invitation_id.as_bytes().to_vec(),
+                    variant_version: variant_version.clone(),
+                    game_id: game_id.as_bytes().to_vec(),
+                };
+                let s2c = S2c::NewGameAccepted(accepted);
+                if let Err(err) = ws_server.send_to(receiver_session, None, s2c).await {
+                    log::debug!("Could not confirm the game to {receiver_id}: {err}");
+                }
+                match inviter.session(&ws_server, sender_id).await {
+                    Ok(Some(sender_session)) => {
+                        let game = AcceptedGame {
+                            sender_session,
+                            receiver_session,
+                        };
+                        accept_game(&ws_server, game_id, peer_id.clone(), game).await;
+                    }
+                    Ok(None) => log::debug!("{sender_id} can't signal, it has no session"),
+                    Err(err) => log::error!("Could not find session of {sender_id}: {err}"),
+                }
+                (Some(peer_id), variant_version, game_id.as_bytes().to_vec())
+            }
+            new_game_response::Answer::Declined => {
+                resolve_invitation(&ws_server, invitation_id, InvitationStatus::Declined).await;
+                (None, String::new(), Vec::new())
+            }
+        };
+        let response = NewGameResponse {
+            answer: Some(answer as i32),
+            peer_id,
+            error: None,
+            invitation_id: Vec::new(),
+            variant_version,
+            game_id,
+        };
+        respond(response).await;
+    });
+    Ok(Ok(invitation_id))
+}
+
+/// Lets both sessions of the game exchange their WebRTC handshake
+async fn accept_game(ws_server: &Websockets, game_id: Uuid, peer_id: Vec<u8>, game: AcceptedGame) {
+    ws_server
+        .accepted_games
+        .insert(game_id, peer_id.clone(), game);
+    for instance_id in [
+        game.sender_session.instance_id,
+        game.receiver_session.instance_id,
+    ] {
+        if instance_id == ws_server.cluster.instance_id {
+            continue;
+        }
+        let message = ClusterMessage::GameAccepted {
+            game_id,
+            peer_id: peer_id.clone(),
+            game,
+        };
+        if let Err(err) = ws_server.cluster.publish(instance_id, &message).await {
+            log::error!("Could not publish accepted game: {err}");
+        }
+    }
+}
+
+/// Records the outcome of the invitation. Failures are only logged, so that
+/// the players aren't kept waiting.
+async fn resolve_invitation(ws_server: &Websockets, invitation_id: Uuid, status: InvitationStatus) {
+    let result = match ws_server.pool.get().await {
+        Ok(mut db) => GameInvitation::resolve(&mut db, invitation_id, status)
+            .await
+            .map_err(AppError::from),
+        Err(err) => Err(err.into()),
+    };
+    if let Err(err) = result {
+        log::error!("Could not resolve invitation {invitation_id}: {err}");
+    }
+}
+
+/// Records the game of the accepted invitation. Returns its id.
+async fn start_game(
+    ws_server: &Websockets,
+    invitation_id: Uuid,
+    peer_id: Vec<u8>,
+    variant_version: String,
+) -> Option<Uuid> {
+    let result = match ws_server.pool.get().await {
+        Ok(mut db) => GameInvitation::accept(&mut db, invitation_id, peer_id, variant_version)
+            .await
+            .map_err(AppError::from),
+        Err(err) => Err(err.into()),
+    };
+    match result {
+        Ok(Some(game)) => {
+            log::debug!("Game {} of invitation {invitation_id} started", game.id);
+            return Some(game.id);
+        }
+        Ok(None) => log::error!("Invitation {invitation_id} was resolved before it was accepted"),
+        Err(err) => log::error!("Could not start game of invitation {invitation_id}: {err}"),
+    }
+    None
+}
+
+async fn cancel_invitation(
+    ws_server: &Websockets,
+    key: &InvitationKey,
+    sessions: impl IntoIterator<Item = WebsocketSessionEntry>,
+    reason: new_game_event_cancelled::Reason,
+) {
+    let cancelled = NewGameEventCancelled {
+        sender_user_id: key.sender_id.as_bytes().to_vec(),
+        request_id: key.request_id,
+        reason: reason as i32,
+    };
+    for session in sessions {
+        let s2c = S2c::NewGameEventCancelled(cancelled.clone());
+        ws_server.send_to(session, None, s2c).await.ok();
+    }
+}
+
 async fn handle_new_game_event_response(
     ws_server: &Arc<Websockets>,
     ws_session: &WebsocketSession,
-    session: &mut Session,
+    request_id: i32,
     response: NewGameEventResponse,
 ) -> Result<(), WebsocketError> {
-    let WebsocketSession {
-        id,
-        session,
-        user_id,
-        last_pinged,
-    } = ws_session;
-    let NewGameEventResponse { answer, peer_id } = response;
-    let answer = new_game_event_response::Answer::try_from(answer)?;
-    match answer {
-        new_game_event_response::Answer::Accept => log::debug!("Accepted!"),
-        new_game_event_response::Answer::Decline => log::debug!("Declined!"),
-    }
-    Ok(())
+    let WebsocketSession { id, user_id, .. } = ws_session;
+    // Validate the answer before it is forwarded to the sender
+    new_game_event_response::Answer::try_from(response.answer)?;
+    let sender_id = Uuid::from_slice(&response.sender_user_id)?;
+    let key = InvitationKey {
+        sender_id,
+        receiver_id: *user_id,
+        request_id: response.request_id,
+    };
+    let session = ws_server.cluster.entry(*id);
+    let encoded_response = response.encode_to_vec();
+    let answer = InvitationAnswer { session, response };
+    let error = match ws_server.pending_invitations.answer(&key, answer) {
+        Ok(()) => return Ok(()),
+        Err(error @ (AnswerError::Expired | AnswerError::Answered)) => error.into(),
+        Err(AnswerError::NotFound) => {
+            // The invitation might be pending on another instance
+            match ws_server.cluster.invitation_instance(&key).await? {
+                Some(instance_id) if instance_id != ws_server.cluster.instance_id => {
+                    let message = ClusterMessage::InvitationAnswer {
+                        key,
+                        session,
+                        request_id,
+                        response: encoded_response,
+                    };
+                    ws_server.cluster.publish(instance_id, &message).await?;
+                    return Ok(());
+                }
+                _ => new_game_event_response_error::Error::NotFound,
+            }
+        }
+    };
+    log::debug!("Session {id}: Rejected answer to invitation {key:?} (User Id: {user_id})");
+    let response_error = NewGameEventResponseError {
+        sender_user_id: key.sender_id.as_bytes().to_vec(),
+        request_id: key.request_id,
+        error: error as i32,
+    };
+    ws_session
+        .reply(request_id, S2c::NewGameEventResponseError(response_error))
+        .await
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Websockets {
     pub sessions: dashmap::DashMap<Uuid, WebsocketSession>,
+    pub pending_invitations: PendingInvitations,
+    pub accepted_games: AcceptedGames,
+    pub cluster: Cluster,
+    pub shutdown: Shutdown,
+    pub queue_metrics: Arc<QueueMetrics>,
+    /// Limits shared by the sessions of a user
+    user_rate_limiters: dashmap::DashMap<Uuid, RateLimiter>,
+    pool: DbPool,
+    config: Config,
+}
+
+impl Websockets {
+    pub fn new(pool: DbPool, config: Config, cluster: Cluster) -> Self {
+        Self {
+            sessions: Default::default(),
+            pending_invitations: Default::default(),
+            accepted_games: Default::default(),
+            cluster,
+            shutdown: Default::default(),
+            queue_metrics: Default::default(),
+            user_rate_limiters: Default::default(),
+            pool,
+            config,
+        }
+    }
+
+    /// Sends `s2c` to a session on any instance. If `reply_to` is set, it
+    /// answers the client message with that id.
+    async fn send_to(
+        &self,
+        entry: WebsocketSessionEntry,
+        reply_to: Option<i32>,
+        s2c: S2c,
+    ) -> Result<(), WebsocketError> {
+        if entry.instance_id != self.cluster.instance_id {
+            return self.cluster.send(entry, reply_to, s2c).await;
+        }
+        let ws_session = self
+            .sessions
+            .get(&entry.session_id)
+            .map(|ws_session| ws_session.value().clone())
+            .ok_or(WebsocketError::SessionNotFound)?;
+        match reply_to {
+            Some(request_id) => ws_session.reply(request_id, s2c).await,
+            None => ws_session.push(s2c).await,
+        }
+    }
+
+    /// Sends `s2c` to all sessions of the user on all instances
+    async fn push_to_user(&self, user_id: Uuid, s2c: S2c) -> Result<(), WebsocketError> {
+        for entry in self.sessions_of_user(user_id).await? {
+            if let Err(err) = self.send_to(entry, None, s2c.clone()).await {
+                log::debug!("Session {}: Could not push: {err}", entry.session_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// All sessions of the user on all instances, local ones first
+    async fn sessions_of_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<WebsocketSessionEntry>, WebsocketError> {
+        let mut entries: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|entry| entry.user_id == user_id)
+            .map(|entry| self.cluster.entry(entry.id))
+            .collect();
+        entries.extend(self.remote_sessions_of_user(user_id).await?);
+        Ok(entries)
+    }
+
+    async fn remote_sessions_of_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<WebsocketSessionEntry>, WebsocketError> {
+        self.cluster
+            .remote_sessions_of_user(user_id, self.session_max_age())
+            .await
+    }
+
+    /// Entries are refreshed with the last activity on every ping and idle
+    /// sessions are closed, so older ones belong to instances, that went down
+    fn session_max_age(&self) -> chrono::Duration {
+        let max_age = self.config.idle_timeout + self.config.ping_interval;
+        chrono::Duration::seconds(max_age.as_secs() as i64)
+    }
+
+    /// The session of the user, that hands out the resume token
+    fn resumable_session(&self, user_id: Uuid, resume_token: Uuid) -> Option<WebsocketSession> {
+        self.sessions
+            .iter()
+            .find(|entry| entry.user_id == user_id && entry.resume_token == resume_token)
+            .map(|entry| entry.value().clone())
+    }
+
+    /// The session of the user, that was active most recently
+    fn latest_session_of_user(&self, user_id: Uuid) -> Option<WebsocketSession> {
+        self.sessions
+            .iter()
+            .filter(|entry| entry.user_id == user_id)
+            .max_by_key(|entry| entry.last_pinged.load(Ordering::Relaxed))
+            .map(|entry| entry.value().clone())
+    }
 }
 
 #[derive(Clone)]
 pub struct WebsocketSession {
     pub id: Uuid,
-    pub session: Session,
     pub user_id: Uuid,
+    /// Lets the client resume the session after its connection dropped
+    pub resume_token: Uuid,
     pub last_pinged: Arc<AtomicIsize>,
+    pub last_push_id: Arc<AtomicI32>,
+    pub connected_at: DateTime<Utc>,
+    outbox: Arc<tokio::sync::Mutex<Outbox>>,
+    rate_limiter: Arc<std::sync::Mutex<SessionRateLimiter>>,
 }
 
 impl WebsocketSession {
+    /// The connection, the session is currently attached to, and its
+    /// generation
+    async fn connection(&self) -> Option<(Session, u64)> {
+        let outbox = self.outbox.lock().await;
+        Some((outbox.session()?, outbox.generation()))
+    }
+
     fn update_pinged(&self) {
         let now = Utc::now().timestamp() as isize;
         self.last_pinged.store(now, Ordering::Relaxed);
     }
+
+    /// Answers the client message with the id `request_id`
+    async fn reply(&self, request_id: i32, s2c: S2c) -> Result<(), WebsocketError> {
+        self.outbox.lock().await.send(request_id, s2c)
+    }
+
+    /// Sends a message, that doesn't answer a client message
+    async fn push(&self, s2c: S2c) -> Result<(), WebsocketError> {
+        let mut outbox = self.outbox.lock().await;
+        let id = self.last_push_id.fetch_sub(1, Ordering::Relaxed) - 1;
+        outbox.send(id, s2c)
+    }
 }
 
 impl Debug for WebsocketSession {
@@ -207,6 +1142,7 @@ impl Debug for WebsocketSession {
         f.debug_struct("WebsocketSession")
             .field("session", &"?")
             .field("last_pinged", &self.last_pinged)
+            .field("outbox", &self.outbox)
             .finish()
     }
 }
@@ -219,6 +1155,24 @@ pub enum WebsocketError {
     ClientEmptyRequest,
     #[error("unsupported-message-type")]
     UnsupportedMessageType,
+    #[error("handshake-required")]
+    HandshakeRequired,
+    #[error("handshake-done")]
+    HandshakeDone,
+    #[error("encoding-mismatch")]
+    EncodingMismatch,
+    #[error("rate-limited")]
+    RateLimited(Duration),
+    #[error("rate-limit-exceeded")]
+    RateLimitExceeded,
+    #[error("frame-too-large")]
+    FrameTooLarge,
+    #[error("not-friends")]
+    NotFriends,
+    #[error("invalid-chat-message")]
+    InvalidChatMessage,
+    #[error("invalid-time-control")]
+    InvalidTimeControl,
     #[error("prost-decode")]
     ProstDecode(#[from] prost::DecodeError),
     #[error("prost-encode")]
@@ -227,4 +1181,83 @@ pub enum WebsocketError {
     ProstUnknownEnumValue(#[from] prost::UnknownEnumValue),
     #[error("actix_ws-closed")]
     WebsocketClosed(#[from] actix_ws::Closed),
+    #[error("invalid-uuid")]
+    InvalidUuid(#[from] uuid::Error),
+    #[error("session-not-found")]
+    SessionNotFound,
+    #[error("redis")]
+    Redis(#[from] redis::RedisError),
+    #[error("serde-json")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("database")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("{}", .0)]
+    App(Box<AppError>),
+}
+
+impl WebsocketError {
+    /// Whether the session can't continue after the error. Other errors are
+    /// reported to the client.
+    fn closes_session(&self) -> bool {
+        use WebsocketError::*;
+        matches!(
+            self,
+            ClientDisconnect
+                | UnsupportedMessageType
+                | WebsocketClosed(_)
+                | RateLimitExceeded
+                | FrameTooLarge
+        )
+    }
+
+    /// The status code, when the error ends a REST request. Failures of the
+    /// server aren't blamed on the client.
+    pub fn status_code(&self) -> StatusCode {
+        use WebsocketError::*;
+        match self {
+            ClientEmptyRequest
+            | UnsupportedMessageType
+            | HandshakeRequired
+            | HandshakeDone
+            | EncodingMismatch
+            | FrameTooLarge
+            | InvalidChatMessage
+            | InvalidTimeControl
+            | ProstDecode(_)
+            | ProstUnknownEnumValue(_)
+            | InvalidUuid(_) => StatusCode::BAD_REQUEST,
+            RateLimited(_) | RateLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+            NotFriends => StatusCode::FORBIDDEN,
+            SessionNotFound | Diesel(diesel::result::Error::NotFound) => StatusCode::NOT_FOUND,
+            ClientDisconnect | ProstEncode(_) | WebsocketClosed(_) | Redis(_) | SerdeJson(_)
+            | Diesel(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            App(err) => err.status_code(),
+        }
+    }
+
+    /// The reason to close the session with, if the error closes it
+    fn close_reason(&self) -> Option<CloseReason> {
+        use WebsocketError::*;
+        let code = match self {
+            RateLimitExceeded => CloseCode::Policy,
+            FrameTooLarge => CloseCode::Size,
+            _ => return None,
+        };
+        Some(CloseReason {
+            code,
+            description: Some(self.to_string()),
+        })
+    }
+}
+
+impl From<AppError> for WebsocketError {
+    fn from(value: AppError) -> Self {
+        WebsocketError::App(Box::new(value))
+    }
+}
+
+impl<E> From<bb8::RunError<E>> for WebsocketError {
+    fn from(value: bb8::RunError<E>) -> Self {
+        AppError::from(value).into()
+    }
 }
@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use semver::Version;
+
+use crate::{
+    db::{games::GameInvitation, variants::VariantVersion},
+    error::AppError,
+};
+
+use super::Websockets;
+
+/// The version of the variant, that is played, or None, if the players have
+/// none in common
+pub async fn negotiate(
+    ws_server: &Websockets,
+    invitation: &GameInvitation,
+    sender_versions: &[String],
+    receiver_versions: &[String],
+) -> Result<Option<String>, AppError> {
+    let mut db = ws_server.pool.get().await?;
+    let published = VariantVersion::list_for_variant(&mut db, invitation.variant_id).await?;
+    Ok(pick_version(
+        &published,
+        &invitation.variant_version,
+        sender_versions,
+        receiver_versions,
+    ))
+}
+
+/// Picks the highest version of the variant, that both players support and
+/// that wasn't withdrawn. Receivers, that report no versions, are assumed to
+/// support the offered one.
+pub fn pick_version(
+    published: &[VariantVersion],
+    offered: &str,
+    sender_versions: &[String],
+    receiver_versions: &[String],
+) -> Option<String> {
+    let parse = |versions: &[String]| -> HashSet<Version> {
+        versions
+            .iter()
+            .filter_map(|version| Version::parse(version.trim()).ok())
+            .collect()
+    };
+    let offered = [offered.to_owned()];
+    let mut sender = parse(&offered);
+    sender.extend(parse(sender_versions));
+    let receiver = match receiver_versions {
+        [] => parse(&offered),
+        versions => parse(versions),
+    };
+    published
+        .iter()
+        .filter(|version| version.withdrawn_at.is_none())
+        .filter_map(|version| Some((version.semver()?, version)))
+        .filter(|(semver, _)| sender.contains(semver) && receiver.contains(semver))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, version)| version.version.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn published_version(version: &str, withdrawn: bool) -> VariantVersion {
+        VariantVersion {
+            variant_id: Uuid::nil(),
+            version: version.to_owned(),
+            created_at: Utc::now(),
+            withdrawn_at: withdrawn.then(Utc::now),
+            bundle_format: None,
+            checksum: None,
+        }
+    }
+
+    fn versions(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|version| version.to_string()).collect()
+    }
+
+    #[test]
+    fn empty_receiver_list_plays_the_offered_version() {
+        let published = [
+            published_version("1.0.0", false),
+            published_version("1.1.0", false),
+        ];
+        let picked = pick_version(&published, "1.0.0", &versions(&["1.1.0"]), &[]);
+        assert_eq!(picked.as_deref(), Some("1.0.0"));
+    }
+
+    #[test]
+    fn withdrawn_versions_are_skipped() {
+        let published = [
+            published_version("1.0.0", false),
+            published_version("1.1.0", true),
+        ];
+        let both = versions(&["1.0.0", "1.1.0"]);
+        let picked = pick_version(&published, "1.1.0", &both, &both);
+        assert_eq!(picked.as_deref(), Some("1.0.0"));
+
+        let only_withdrawn = [published_version("1.1.0", true)];
+        let picked = pick_version(&only_withdrawn, "1.1.0", &both, &both);
+        assert_eq!(picked, None);
+    }
+
+    #[test]
+    fn unparsable_versions_are_ignored() {
+        let published = [
+            published_version("1.0.0", false),
+            published_version("latest", false),
+        ];
+        let both = versions(&["latest", "1.0.0", "not a version"]);
+        let picked = pick_version(&published, "latest", &both, &both);
+        assert_eq!(picked.as_deref(), Some("1.0.0"));
+
+        let picked = pick_version(&published, "1.0.0", &[], &versions(&["1.0"]));
+        assert_eq!(picked, None);
+    }
+
+    #[test]
+    fn highest_shared_version_is_picked() {
+        let published = [
+            published_version("1.0.0", false),
+            published_version("1.2.0", false),
+            published_version("2.0.0", false),
+            published_version("1.10.0", false),
+        ];
+        let sender = versions(&["1.0.0", "1.2.0", "1.10.0", "2.0.0"]);
+        let receiver = versions(&["1.2.0", " 1.10.0 ", "1.0.0"]);
+        let picked = pick_version(&published, "1.0.0", &sender, &receiver);
+        assert_eq!(picked.as_deref(), Some("1.10.0"));
+    }
+}
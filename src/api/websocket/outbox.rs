@@ -0,0 +1,332 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use actix_ws::{CloseCode, CloseReason, Closed, Session};
+use chrono::{DateTime, Utc};
+use p2pcv_protobuf::server_to_client::{self, msg::S2c, SessionStarted};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+};
+
+use super::{encoding::Encoding, WebsocketError};
+
+/// How long a closing connection waits for its queued messages to be written
+const FLUSH_TIMEOUT_SECS: u64 = 5;
+
+/// The connection of a session and the messages, that were sent over it. The
+/// messages are kept, so that they can be replayed, when the client resumes
+/// the session after its connection dropped.
+pub struct Outbox {
+    /// `None` while the session is detached from its connection
+    session: Option<Session>,
+    /// `None`, if the connection overflowed
+    writer: Option<Writer>,
+    encoding: Encoding,
+    /// Counts the connections, the session was attached to
+    generation: u64,
+    detached_at: Option<DateTime<Utc>>,
+    messages: VecDeque<server_to_client::Msg>,
+    last_seq: u64,
+    capacity: usize,
+    queue_size: usize,
+    metrics: Arc<QueueMetrics>,
+}
+
+impl std::fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbox")
+            .field("encoding", &self.encoding)
+            .field("generation", &self.generation)
+            .field("detached_at", &self.detached_at)
+            .field("last_seq", &self.last_seq)
+            .field("queue_depth", &self.queue_depth())
+            .finish()
+    }
+}
+
+impl Outbox {
+    pub fn new(
+        session: Session,
+        encoding: Encoding,
+        capacity: usize,
+        queue_size: usize,
+        metrics: Arc<QueueMetrics>,
+    ) -> Self {
+        let writer = Writer::spawn(
+            session.clone(),
+            encoding,
+            queue_size,
+            metrics.clone(),
+            Vec::new(),
+        );
+        Outbox {
+            session: Some(session),
+            writer: Some(writer),
+            encoding,
+            generation: 0,
+            detached_at: None,
+            messages: VecDeque::with_capacity(capacity),
+            last_seq: 0,
+            capacity,
+            queue_size,
+            metrics,
+        }
+    }
+
+    /// The connection, the session is currently attached to
+    pub fn session(&self) -> Option<Session> {
+        self.session.clone()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// How many messages wait for the writer of the connection
+    pub fn queue_depth(&self) -> usize {
+        self.writer
+            .as_ref()
+            .map_or(0, |writer| self.queue_size - writer.queue.capacity())
+    }
+
+    /// Numbers, buffers and queues the message. While the session is
+    /// detached, the message is only buffered. If the client doesn't keep
+    /// up, presence updates are dropped and the connection is closed for
+    /// other messages. The session can be resumed in that case.
+    pub fn send(&mut self, id: i32, s2c: S2c) -> Result<(), WebsocketError> {
+        let full = self
+            .writer
+            .as_ref()
+            .is_some_and(|writer| writer.queue.capacity() == 0);
+        if full && matches!(s2c, S2c::FriendPresence(_)) {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.last_seq += 1;
+        let msg = server_to_client::Msg {
+            id,
+            s2c: Some(s2c),
+            seq: self.last_seq,
+        };
+        if self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(msg.clone());
+        let Some(Writer { queue, .. }) = &self.writer else {
+            return Ok(());
+        };
+        // Counted first, since the writer might take the message right away
+        self.metrics.queued.fetch_add(1, Ordering::Relaxed);
+        let result = queue.try_send(msg);
+        let depth = self.queue_size - queue.capacity();
+        if result.is_err() {
+            self.metrics.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        match result {
+            Ok(()) => {
+                self.metrics.peak_depth.fetch_max(depth, Ordering::Relaxed);
+            }
+            Err(TrySendError::Full(_)) => self.overflow(),
+            // The reader of the connection detaches the session, once it
+            // notices, that the connection dropped
+            Err(TrySendError::Closed(_)) => self.writer = None,
+        }
+        Ok(())
+    }
+
+    /// Closes the connection of a client, that doesn't keep up. Its reader
+    /// detaches the session afterwards.
+    fn overflow(&mut self) {
+        self.writer = None;
+        self.metrics.overflowed.fetch_add(1, Ordering::Relaxed);
+        let Some(session) = self.session.clone() else {
+            return;
+        };
+        log::info!("Outbound queue overflowed - Closing connection");
+        let reason = CloseReason {
+            code: CloseCode::Again,
+            description: Some("slow-consumer".to_owned()),
+        };
+        actix_web::rt::spawn(async move {
+            session.close(Some(reason)).await.ok();
+        });
+    }
+
+    /// Separates the session from the connection of the generation and
+    /// returns it. Nothing happens, if the session was attached to another
+    /// connection in the meantime.
+    pub fn detach(&mut self, generation: u64) -> Option<Session> {
+        if generation != self.generation {
+            return None;
+        }
+        let session = self.session.take()?;
+        self.writer = None;
+        self.detached_at = Some(Utc::now());
+        Some(session)
+    }
+
+    /// Separates the session from its connection for good. The connection
+    /// is closed, once the queued messages are written.
+    pub fn close(&mut self) -> Option<Closing> {
+        let session = self.session.take()?;
+        let writer = self.writer.take().map(|writer| writer.handle);
+        Some(Closing { session, writer })
+    }
+
+    /// Whether the session is detached for at least `grace_period`
+    pub fn detached_for(&self, grace_period: chrono::Duration) -> bool {
+        self.detached_at
+            .is_some_and(|detached_at| Utc::now() - detached_at >= grace_period)
+    }
+
+    /// Attaches the session to a new connection and replays the messages
+    /// after `last_seq`. Returns the new generation and the connection, that
+    /// was replaced, if the old one wasn't noticed to be dropped yet.
+    pub fn attach(
+        &mut self,
+        session: Session,
+        encoding: Encoding,
+        last_seq: u64,
+        mut started: SessionStarted,
+    ) -> (u64, Option<Session>) {
+        let first_seq = self
+            .messages
+            .front()
+            .map_or(self.last_seq + 1, |msg| msg.seq);
+        started.resumed = true;
+        started.messages_lost = last_seq + 1 < first_seq;
+        let msg = server_to_client::Msg {
+            id: 0,
+            s2c: Some(S2c::SessionStarted(started)),
+            seq: 0,
+        };
+        // The writer replays a snapshot before the queue, since the replay
+        // might not fit into it. That way the outbox isn't locked meanwhile.
+        let replay = std::iter::once(msg)
+            .chain(
+                self.messages
+                    .iter()
+                    .filter(|msg| msg.seq > last_seq)
+                    .cloned(),
+            )
+            .collect();
+        let writer = Writer::spawn(
+            session.clone(),
+            encoding,
+            self.queue_size,
+            self.metrics.clone(),
+            replay,
+        );
+        self.writer = Some(writer);
+        let replaced = self.session.replace(session);
+        self.encoding = encoding;
+        self.generation += 1;
+        self.detached_at = None;
+        (self.generation, replaced)
+    }
+}
+
+/// Writes the queued messages to the connection, so that a slow client only
+/// holds up its own session
+struct Writer {
+    queue: mpsc::Sender<server_to_client::Msg>,
+    handle: JoinHandle<()>,
+}
+
+impl Writer {
+    /// Writes the `replay` first and the queued messages afterwards. The
+    /// writer stops, once the queue is dropped or the connection fails.
+    fn spawn(
+        mut session: Session,
+        encoding: Encoding,
+        queue_size: usize,
+        metrics: Arc<QueueMetrics>,
+        replay: Vec<server_to_client::Msg>,
+    ) -> Self {
+        let (queue, mut rx) = mpsc::channel::<server_to_client::Msg>(queue_size);
+        let handle = actix_web::rt::spawn(async move {
+            'write: {
+                for msg in replay {
+                    let seq = msg.seq;
+                    if let Err(err) = encoding.send(&mut session, msg).await {
+                        log::debug!("Could not replay message {seq}: {err}");
+                        break 'write;
+                    }
+                }
+                while let Some(msg) = rx.recv().await {
+                    metrics.queued.fetch_sub(1, Ordering::Relaxed);
+                    let seq = msg.seq;
+                    if let Err(err) = encoding.send(&mut session, msg).await {
+                        log::debug!("Could not send message {seq}: {err}");
+                        break;
+                    }
+                }
+            }
+            rx.close();
+            while rx.try_recv().is_ok() {
+                metrics.queued.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+        Writer { queue, handle }
+    }
+}
+
+/// A connection, that is closed after its queued messages were written
+pub struct Closing {
+    session: Session,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Closing {
+    /// Waits a bounded time for the queued messages and closes the
+    /// connection
+    pub async fn close(self, reason: Option<CloseReason>) -> Result<(), Closed> {
+        if let Some(writer) = self.writer {
+            let timeout = Duration::from_secs(FLUSH_TIMEOUT_SECS);
+            if tokio::time::timeout(timeout, writer).await.is_err() {
+                log::debug!("Queued messages were not written in time");
+            }
+        }
+        self.session.close(reason).await
+    }
+}
+
+/// Counters for the outbound queues of all sessions
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    /// Messages, that wait for a writer
+    pub queued: AtomicUsize,
+    /// Deepest a single queue has been
+    pub peak_depth: AtomicUsize,
+    /// Presence updates, that were dropped, because a queue was full
+    pub dropped: AtomicU64,
+    /// Connections, that were closed, because a queue was full
+    pub overflowed: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueMetricsSnapshot {
+    pub queued: usize,
+    pub peak_depth: usize,
+    pub dropped: u64,
+    pub overflowed: u64,
+}
+
+impl QueueMetrics {
+    pub fn snapshot(&self) -> QueueMetricsSnapshot {
+        QueueMetricsSnapshot {
+            queued: self.queued.load(Ordering::Relaxed),
+            peak_depth: self.peak_depth.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            overflowed: self.overflowed.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use chrono::{DateTime, Utc};
+use p2pcv_protobuf::server_to_client::{friend_presence, msg::S2c, FriendPresence};
+use uuid::Uuid;
+
+use crate::db::users::User;
+
+use super::{WebsocketError, Websockets};
+
+/// The published presence is kept for this many session max ages
+const PUBLISHED_PRESENCE_TTL_FACTOR: i32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Presence {
+    Online,
+    /// Connected, but the sessions didn't respond for a while
+    Away,
+    Offline,
+}
+
+impl Presence {
+    fn from_last_active(last_active: Option<DateTime<Utc>>, away_after: chrono::Duration) -> Self {
+        match last_active {
+            None => Presence::Offline,
+            Some(last_active) if Utc::now() - last_active > away_after => Presence::Away,
+            Some(_) => Presence::Online,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Presence::Online => "online",
+            Presence::Away => "away",
+            Presence::Offline => "offline",
+        }
+    }
+}
+
+impl From<Presence> for friend_presence::Presence {
+    fn from(value: Presence) -> Self {
+        match value {
+            Presence::Online => friend_presence::Presence::Online,
+            Presence::Away => friend_presence::Presence::Away,
+            Presence::Offline => friend_presence::Presence::Offline,
+        }
+    }
+}
+
+impl Websockets {
+    /// The presence of the user over all their sessions on all instances
+    pub async fn presence_of_user(&self, user_id: Uuid) -> Result<Presence, WebsocketError> {
+        let local = self.latest_session_of_user(user_id).and_then(|ws_session| {
+            let last_pinged = ws_session.last_pinged.load(Ordering::Relaxed);
+            DateTime::from_timestamp(last_pinged as i64, 0)
+        });
+        let remote = self
+            .cluster
+            .last_active_of_user(user_id, self.session_max_age())
+            .await?;
+        let away_after = chrono::Duration::seconds(self.config.away_after.as_secs() as i64);
+        Ok(Presence::from_last_active(local.max(remote), away_after))
+    }
+}
+
+/// Pushes the presence of the user to the sessions of their friends, if it
+/// changed since it was published last.
+pub async fn publish(ws_server: &Arc<Websockets>, user_id: Uuid) -> Result<(), WebsocketError> {
+    let presence = ws_server.presence_of_user(user_id).await?;
+    let ttl = ws_server.session_max_age() * PUBLISHED_PRESENCE_TTL_FACTOR;
+    let previous = ws_server
+        .cluster
+        .swap_published_presence(user_id, presence.as_str(), ttl)
+        .await?;
+    if previous.as_deref() == Some(presence.as_str()) {
+        return Ok(());
+    }
+    log::debug!("User {user_id} is {}", presence.as_str());
+
+    let mut db = ws_server.pool.get().await?;
+    let friends = User::list_friends_by_user_id(&mut db, user_id).await?;
+    drop(db);
+
+    let friend_presence = FriendPresence {
+        user_id: user_id.as_bytes().to_vec(),
+        presence: friend_presence::Presence::from(presence) as i32,
+    };
+    for entry in friends {
+        let s2c = S2c::FriendPresence(friend_presence.clone());
+        if let Err(err) = ws_server.push_to_user(entry.friend.id, s2c).await {
+            log::error!(
+                "Could not push presence of {user_id} to {}: {err}",
+                entry.friend.id
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, time::Duration};
+
+use p2pcv_protobuf::client_to_server::msg::C2s;
+use tokio::time::Instant;
+
+use super::{config::Limit, WebsocketError, WebsocketSession, Websockets};
+
+/// Kinds of client messages, that are limited separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    NewGame,
+    NewGameEventResponse,
+    Signaling,
+    Hello,
+    ChatMessage,
+    GameResult,
+    /// Messages, that can't be handled, e.g. because they don't decode
+    Invalid,
+}
+
+impl From<&C2s> for RequestKind {
+    fn from(value: &C2s) -> Self {
+        match value {
+            C2s::NewGame(_) => RequestKind::NewGame,
+            C2s::NewGameEventResponse(_) => RequestKind::NewGameEventResponse,
+            C2s::SdpOffer(_) | C2s::SdpAnswer(_) | C2s::IceCandidate(_) => RequestKind::Signaling,
+            C2s::Hello(_) => RequestKind::Hello,
+            C2s::SendChatMessage(_) => RequestKind::ChatMessage,
+            C2s::ReportGameResult(_) => RequestKind::GameResult,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: Limit, now: Instant) -> Self {
+        TokenBucket {
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    /// Adds the tokens, that accumulated since the last update
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let per_sec = limit.per_minute as f64 / 60.0;
+        let refilled = now.duration_since(self.updated_at).as_secs_f64() * per_sec;
+        self.tokens = (self.tokens + refilled).min(limit.burst as f64);
+        self.updated_at = now;
+    }
+
+    /// How long it takes until the next token is available, if the bucket is
+    /// empty
+    fn retry_after(&self, limit: Limit) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        let per_sec = limit.per_minute as f64 / 60.0;
+        Some(Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+    }
+
+    /// Takes a token, if one is available
+    fn take(&mut self, limit: Limit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if let Some(retry_after) = self.retry_after(limit) {
+            return Err(retry_after);
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// A token bucket for every kind of client message
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<RequestKind, TokenBucket>,
+}
+
+impl RateLimiter {
+    /// The refilled bucket of the kind
+    fn bucket(&mut self, kind: RequestKind, limit: Limit, now: Instant) -> &mut TokenBucket {
+        let bucket = self
+            .buckets
+            .entry(kind)
+            .or_insert_with(|| TokenBucket::full(limit, now));
+        bucket.refill(limit, now);
+        bucket
+    }
+}
+
+/// The limits of a session and how often it exceeded them
+#[derive(Debug)]
+pub struct SessionRateLimiter {
+    limiter: RateLimiter,
+    strikes: TokenBucket,
+}
+
+impl SessionRateLimiter {
+    pub fn new(strikes: Limit) -> Self {
+        SessionRateLimiter {
+            limiter: Default::default(),
+            strikes: TokenBucket::full(strikes, Instant::now()),
+        }
+    }
+}
+
+/// Counts the request against the limits of the session and of its user. A
+/// token is only taken, if both allow the request. Sessions, that keep
+/// exceeding their limits, are closed.
+pub fn check(
+    ws_server: &Websockets,
+    ws_session: &WebsocketSession,
+    kind: RequestKind,
+) -> Result<(), WebsocketError> {
+    let limits = &ws_server.config.rate_limits;
+    let (session_limit, user_limit) = (limits.session.get(kind), limits.user.get(kind));
+    let now = Instant::now();
+    let mut session_limiter = ws_session.rate_limiter.lock().unwrap();
+    let mut user_limiter = ws_server
+        .user_rate_limiters
+        .entry(ws_session.user_id)
+        .or_default();
+    let session_bucket = session_limiter.limiter.bucket(kind, session_limit, now);
+    let user_bucket = user_limiter.bucket(kind, user_limit, now);
+    let retry_after = session_bucket
+        .retry_after(session_limit)
+        .max(user_bucket.retry_after(user_limit));
+    let Some(retry_after) = retry_after else {
+        session_bucket.tokens -= 1.0;
+        user_bucket.tokens -= 1.0;
+        return Ok(());
+    };
+    drop(user_limiter);
+    if session_limiter.strikes.take(limits.strikes, now).is_err() {
+        return Err(WebsocketError::RateLimitExceeded);
+    }
+    Err(WebsocketError::RateLimited(retry_after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Limit = Limit::new(3, 60);
+
+    #[test]
+    fn full_bucket_allows_a_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, now);
+        for _ in 0..LIMIT.burst {
+            assert!(bucket.take(LIMIT, now).is_ok());
+        }
+        assert_eq!(bucket.take(LIMIT, now), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn empty_bucket_refills_over_time() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, now);
+        for _ in 0..LIMIT.burst {
+            bucket.take(LIMIT, now).unwrap();
+        }
+        let later = now + Duration::from_millis(500);
+        assert_eq!(bucket.take(LIMIT, later), Err(Duration::from_millis(500)));
+        let later = now + Duration::from_secs(1);
+        assert!(bucket.take(LIMIT, later).is_ok());
+        assert!(bucket.take(LIMIT, later).is_err());
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, now);
+        bucket.take(LIMIT, now).unwrap();
+        let later = now + Duration::from_secs(3600);
+        for _ in 0..LIMIT.burst {
+            assert!(bucket.take(LIMIT, later).is_ok());
+        }
+        assert!(bucket.take(LIMIT, later).is_err());
+    }
+
+    #[test]
+    fn rejected_requests_take_no_token() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::full(LIMIT, now);
+        for _ in 0..LIMIT.burst {
+            bucket.take(LIMIT, now).unwrap();
+        }
+        for _ in 0..10 {
+            assert!(bucket.take(LIMIT, now).is_err());
+        }
+        let later = now + Duration::from_secs(1);
+        assert!(bucket.take(LIMIT, later).is_ok());
+    }
+
+    #[test]
+    fn kinds_have_separate_buckets() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::default();
+        for _ in 0..LIMIT.burst {
+            limiter
+                .bucket(RequestKind::NewGame, LIMIT, now)
+                .take(LIMIT, now)
+                .unwrap();
+        }
+        let bucket = limiter.bucket(RequestKind::NewGame, LIMIT, now);
+        assert!(bucket.retry_after(LIMIT).is_some());
+        let bucket = limiter.bucket(RequestKind::ChatMessage, LIMIT, now);
+        assert!(bucket.retry_after(LIMIT).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::HashSet,
+    sync::{atomic::Ordering, Arc},
+};
+
+use actix_ws::{CloseCode, CloseReason};
+use chrono::{DateTime, Utc};
+
+use super::{detach_session, presence, Websockets};
+
+/// Pings all sessions regularly and detaches the ones, that stayed silent for
+/// longer than the idle timeout, from their connection. Changes of the
+/// presence, that come with silent sessions, are published to the friends.
+/// The depth of the outbound queues is logged.
+pub fn spawn(ws_server: Arc<Websockets>) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(ws_server.config.ping_interval);
+        loop {
+            interval.tick().await;
+            reap(&ws_server).await;
+        }
+    });
+}
+
+async fn reap(ws_server: &Arc<Websockets>) {
+    let idle_timeout = ws_server.config.idle_timeout.as_secs() as isize;
+    let now = Utc::now().timestamp() as isize;
+    // Collect first, so that no lock on the map is held across an await
+    let sessions: Vec<_> = ws_server
+        .sessions
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    let user_ids: HashSet<_> = sessions
+        .iter()
+        .map(|ws_session| ws_session.user_id)
+        .collect();
+    for ws_session in sessions {
+        // Detached sessions are closed, once their grace period ends
+        let Some((mut session, generation)) = ws_session.connection().await else {
+            continue;
+        };
+        let last_pinged = ws_session.last_pinged.load(Ordering::Relaxed);
+        if now - last_pinged > idle_timeout {
+            let id = ws_session.id;
+            let user_id = ws_session.user_id;
+            log::info!(
+                "Session {id}: Idle for {}s - Detaching (User Id: {user_id})",
+                now - last_pinged
+            );
+            let reason = CloseReason {
+                code: CloseCode::Normal,
+                description: Some("idle-timeout".to_owned()),
+            };
+            detach_session(ws_server, ws_session, generation, Some(reason)).await;
+            continue;
+        }
+        if session.ping(b"").await.is_err() {
+            detach_session(ws_server, ws_session, generation, None).await;
+            continue;
+        }
+        // Keep the session visible to the other instances
+        let last_active = DateTime::from_timestamp(last_pinged as i64, 0).unwrap_or_default();
+        if let Err(err) = ws_server
+            .cluster
+            .register(ws_session.id, ws_session.user_id, last_active)
+            .await
+        {
+            log::error!(
+                "Session {}: Could not refresh registration: {err}",
+                ws_session.id
+            );
+        }
+    }
+    for user_id in user_ids {
+        if let Err(err) = presence::publish(ws_server, user_id).await {
+            log::error!("Could not publish presence of {user_id}: {err}");
+        }
+    }
+    let metrics = ws_server.queue_metrics.snapshot();
+    log::debug!("Outbound queues: {metrics:?}");
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use actix_ws::{CloseCode, CloseReason};
+use p2pcv_protobuf::server_to_client::{msg::S2c, GoingAway};
+use rand::Rng;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+};
+
+use super::{close_session, Websockets};
+
+/// Up to how long the clients are told to wait before they reconnect
+const MAX_RECONNECT_AFTER_MS: u32 = 5000;
+
+/// Tracks the work, that a shutdown has to wait for
+#[derive(Debug)]
+pub struct Shutdown {
+    /// Taken, once the shutdown started
+    tx: std::sync::Mutex<Option<mpsc::Sender<()>>>,
+    /// Yields `None`, once all guards are dropped
+    rx: tokio::sync::Mutex<mpsc::Receiver<()>>,
+}
+
+/// Keeps the shutdown waiting, until it's dropped
+pub struct ShutdownGuard {
+    _tx: mpsc::Sender<()>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel(1);
+        Shutdown {
+            tx: std::sync::Mutex::new(Some(tx)),
+            rx: tokio::sync::Mutex::new(rx),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn is_draining(&self) -> bool {
+        self.tx.lock().unwrap().is_none()
+    }
+
+    /// `None`, if the shutdown already started
+    pub fn guard(&self) -> Option<ShutdownGuard> {
+        let tx = self.tx.lock().unwrap().clone()?;
+        Some(ShutdownGuard { _tx: tx })
+    }
+
+    fn start(&self) {
+        self.tx.lock().unwrap().take();
+    }
+
+    async fn guards_dropped(&self) {
+        self.rx.lock().await.recv().await;
+    }
+}
+
+/// Resolves, when the process is asked to terminate
+pub async fn signal_received() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM.");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+/// Stops accepting connections and invitations, gives pending invitations an
+/// outcome and closes all sessions with a hint to reconnect. The close frames
+/// are flushed, while the http server shuts down gracefully.
+pub async fn drain(ws_server: &Arc<Websockets>) {
+    ws_server.shutdown.start();
+    log::info!("Draining {} websocket sessions", ws_server.sessions.len());
+
+    // The senders of the invitations notify both sides, once they stop waiting
+    ws_server.pending_invitations.expire_all();
+    let timeout = ws_server.config.shutdown_timeout;
+    if tokio::time::timeout(timeout, ws_server.shutdown.guards_dropped())
+        .await
+        .is_err()
+    {
+        log::warn!("Pending invitations were not closed in time");
+    }
+
+    let sessions: Vec<_> = ws_server
+        .sessions
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    let closed = sessions.into_iter().map(|ws_session| async move {
+        let going_away = GoingAway {
+            reconnect_after_ms: rand::thread_rng().gen_range(0..=MAX_RECONNECT_AFTER_MS),
+        };
+        if let Err(err) = ws_session.push(S2c::GoingAway(going_away)).await {
+            log::debug!("Session {}: Could not say goodbye: {err}", ws_session.id);
+        }
+        let reason = CloseReason {
+            code: CloseCode::Away,
+            description: Some("server-shutdown".to_owned()),
+        };
+        close_session(ws_server, ws_session, Some(reason))
+            .await
+            .ok();
+    });
+    // Closed at once, so that the queues are flushed in parallel
+    futures::future::join_all(closed).await;
+}
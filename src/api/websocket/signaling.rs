@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use crate::redis_db::websocket_sessions::WebsocketSessionEntry;
+use dashmap::DashMap;
+use p2pcv_protobuf::{
+    client_to_server,
+    server_to_client::{self, msg::S2c, signaling_error, SignalingError},
+};
+
+use uuid::Uuid;
+
+use super::{WebsocketError, WebsocketSession, Websockets};
+
+/// A game, that was accepted by the receiver of the invitation. The two
+/// sessions exchange their WebRTC handshake through the server.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptedGame {
+    pub sender_session: WebsocketSessionEntry,
+    pub receiver_session: WebsocketSessionEntry,
+}
+
+impl AcceptedGame {
+    /// The session on the other side of the game, if `session` takes part in
+    /// it
+    fn other_session(&self, session: WebsocketSessionEntry) -> Option<WebsocketSessionEntry> {
+        if session == self.sender_session {
+            Some(self.receiver_session)
+        } else if session == self.receiver_session {
+            Some(self.sender_session)
+        } else {
+            None
+        }
+    }
+}
+
+/// The accepted games by their id, together with the peer id, that their
+/// signals carry
+#[derive(Debug, Default)]
+pub struct AcceptedGames {
+    games: DashMap<Uuid, (Vec<u8>, AcceptedGame)>,
+}
+
+impl AcceptedGames {
+    pub fn insert(&self, game_id: Uuid, peer_id: Vec<u8>, game: AcceptedGame) {
+        self.games.insert(game_id, (peer_id, game));
+    }
+
+    /// Forgets a game, once it's over
+    pub fn remove(&self, game_id: Uuid) {
+        self.games.remove(&game_id);
+    }
+
+    /// Forgets all games, in which the session takes part
+    pub fn remove_session(&self, session: WebsocketSessionEntry) {
+        self.games
+            .retain(|_, (_, game)| game.other_session(session).is_none());
+    }
+
+    /// Peer ids are chosen by the clients, so only the games of the session
+    /// are considered
+    fn other_session(
+        &self,
+        peer_id: &[u8],
+        session: WebsocketSessionEntry,
+    ) -> Option<WebsocketSessionEntry> {
+        self.games.iter().find_map(|entry| {
+            let (game_peer_id, game) = entry.value();
+            if game_peer_id != peer_id {
+                return None;
+            }
+            game.other_session(session)
+        })
+    }
+}
+
+pub(super) enum Signal {
+    SdpOffer(client_to_server::SdpOffer),
+    SdpAnswer(client_to_server::SdpAnswer),
+    IceCandidate(client_to_server::IceCandidate),
+}
+
+impl Signal {
+    fn peer_id(&self) -> &[u8] {
+        match self {
+            Signal::SdpOffer(offer) => &offer.peer_id,
+            Signal::SdpAnswer(answer) => &answer.peer_id,
+            Signal::IceCandidate(candidate) => &candidate.peer_id,
+        }
+    }
+}
+
+impl From<Signal> for S2c {
+    fn from(value: Signal) -> Self {
+        match value {
+            Signal::SdpOffer(client_to_server::SdpOffer { peer_id, sdp }) => {
+                S2c::SdpOffer(server_to_client::SdpOffer { peer_id, sdp })
+            }
+            Signal::SdpAnswer(client_to_server::SdpAnswer { peer_id, sdp }) => {
+                S2c::SdpAnswer(server_to_client::SdpAnswer { peer_id, sdp })
+            }
+            Signal::IceCandidate(client_to_server::IceCandidate {
+                peer_id,
+                candidate,
+                sdp_mid,
+                sdp_m_line_index,
+                username_fragment,
+            }) => S2c::IceCandidate(server_to_client::IceCandidate {
+                peer_id,
+                candidate,
+                sdp_mid,
+                sdp_m_line_index,
+                username_fragment,
+            }),
+        }
+    }
+}
+
+/// Relays a signaling message to the other session of the accepted game
+pub(super) async fn handle_signal(
+    ws_server: &Arc<Websockets>,
+    ws_session: &WebsocketSession,
+    request_id: i32,
+    signal: Signal,
+) -> Result<(), WebsocketError> {
+    let WebsocketSession { id, user_id, .. } = ws_session;
+    let peer_id = signal.peer_id().to_vec();
+    let session = ws_server.cluster.entry(*id);
+    let Some(other_session) = ws_server.accepted_games.other_session(&peer_id, session) else {
+        log::debug!("Session {id}: Signal for unknown peer (User Id: {user_id})");
+        let error = signaling_error::Error::UnknownPeer;
+        return send_signaling_error(ws_session, request_id, peer_id, error).await;
+    };
+    if ws_server
+        .send_to(other_session, None, signal.into())
+        .await
+        .is_err()
+    {
+        let error = signaling_error::Error::PeerOffline;
+        return send_signaling_error(ws_session, request_id, peer_id, error).await;
+    }
+    Ok(())
+}
+
+async fn send_signaling_error(
+    ws_session: &WebsocketSession,
+    request_id: i32,
+    peer_id: Vec<u8>,
+    error: signaling_error::Error,
+) -> Result<(), WebsocketError> {
+    let error = SignalingError {
+        peer_id,
+        error: error as i32,
+    };
+    ws_session
+        .reply(request_id, S2c::SignalingError(error))
+        .await
+}
@@ -1 +1,2 @@
 pub mod sql_functions;
+pub mod text_enum;
@@ -63,26 +63,29 @@ impl FriendRequest {
     pub async fn insert(
         conn: &mut AsyncPgConnection,
         new_friend_request: NewFriendRequest,
-    ) -> QueryResult<()> {
+    ) -> QueryResult<FriendRequest> {
         use db_friend_requests::dsl::*;
         insert_into(friend_requests)
             .values(&new_friend_request)
-            .execute(conn)
-            .await?;
-        Ok(())
+            .returning(FriendRequest::as_returning())
+            .get_result(conn)
+            .await
     }
 
+    /// Deletes the request and returns it, if it existed
     pub async fn delete_by_user_ids(
         conn: &mut AsyncPgConnection,
         sender_u_id: Uuid,
         receiver_u_id: Uuid,
-    ) -> QueryResult<usize> {
+    ) -> QueryResult<Option<FriendRequest>> {
         use db_friend_requests::dsl::*;
         delete(friend_requests)
             .filter(sender_id.eq(sender_u_id))
             .filter(receiver_id.eq(receiver_u_id))
-            .execute(conn)
+            .returning(FriendRequest::as_returning())
+            .get_result(conn)
             .await
+            .optional()
     }
 
     pub async fn exists(
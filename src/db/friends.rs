@@ -57,19 +57,19 @@ impl Friends {
         Ok(())
     }
 
+    /// Returns how many friendships were deleted
     pub async fn delete(
         conn: &mut AsyncPgConnection,
         query_user_id: Uuid,
         query_other_user_id: Uuid,
-    ) -> QueryResult<()> {
+    ) -> QueryResult<usize> {
         use db_friends::dsl::*;
         let (query_user1_id, query_user2_id) = sort_tuple((query_user_id, query_other_user_id));
         delete(friends)
             .filter(user1_id.eq(query_user1_id))
             .filter(user2_id.eq(query_user2_id))
             .execute(conn)
-            .await?;
-        Ok(())
+            .await
     }
 }
 
@@ -0,0 +1,383 @@
+use chrono::{DateTime, Utc};
+use diesel::{insert_into, prelude::*, update};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use uuid::Uuid;
+
+use crate::{app_result::AppResult, error::AppError};
+
+use super::{
+    extensions::text_enum::text_enum,
+    ratings::Rating,
+    schema::{
+        game_disputes as db_game_disputes, game_invitations as db_game_invitations,
+        game_reports as db_game_reports, games as db_games,
+    },
+};
+
+text_enum! {
+    pub enum InvitationStatus {
+        Pending = "pending",
+        Accepted = "accepted",
+        Declined = "declined",
+        TimedOut = "timed-out",
+        /// The invitation could not be delivered or the server shut down
+        Cancelled = "cancelled",
+        /// The players have no version of the variant in common
+        Incompatible = "incompatible",
+    }
+}
+
+text_enum! {
+    pub enum GameStatus {
+        InProgress = "in-progress",
+        Finished = "finished",
+        /// The players reported different results
+        Disputed = "disputed",
+    }
+}
+
+text_enum! {
+    /// The result of a game from the view of its sender
+    pub enum Outcome {
+        SenderWon = "sender-won",
+        ReceiverWon = "receiver-won",
+        Draw = "draw",
+        Aborted = "aborted",
+    }
+}
+
+text_enum! {
+    pub enum Termination {
+        Checkmate = "checkmate",
+        Resignation = "resignation",
+        Timeout = "timeout",
+        Stalemate = "stalemate",
+        Agreement = "agreement",
+        Repetition = "repetition",
+        InsufficientMaterial = "insufficient-material",
+        Abandonment = "abandonment",
+        Other = "other",
+    }
+}
+
+/// The result of a game from the view of the player, that reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlayerResult {
+    Win,
+    Loss,
+    Draw,
+    Abort,
+}
+
+impl Outcome {
+    pub fn of(result: PlayerResult, reported_by_sender: bool) -> Outcome {
+        match (result, reported_by_sender) {
+            (PlayerResult::Win, true) | (PlayerResult::Loss, false) => Outcome::SenderWon,
+            (PlayerResult::Win, false) | (PlayerResult::Loss, true) => Outcome::ReceiverWon,
+            (PlayerResult::Draw, _) => Outcome::Draw,
+            (PlayerResult::Abort, _) => Outcome::Aborted,
+        }
+    }
+}
+
+/// Where a game stands after a result was reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportStatus {
+    /// Waiting for the report of the other player
+    PendingConfirmation,
+    Finished,
+    Disputed,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeControl {
+    pub initial_secs: i32,
+    pub increment_secs: i32,
+}
+
+impl TimeControl {
+    pub fn is_valid(&self) -> bool {
+        self.initial_secs > 0 && self.increment_secs >= 0
+    }
+}
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_game_invitations)]
+pub struct GameInvitation {
+    pub id: Uuid,
+    pub sender_id: Uuid,
+    pub receiver_id: Uuid,
+    pub variant_id: Uuid,
+    pub variant_version: String,
+    pub status: InvitationStatus,
+    pub created_at: DateTime<Utc>,
+    /// When the invitation stopped being pending
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// Both time control fields are None for untimed games
+    pub initial_secs: Option<i32>,
+    pub increment_secs: Option<i32>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_game_invitations)]
+pub struct NewGameInvitation {
+    pub sender_id: Uuid,
+    pub receiver_id: Uuid,
+    pub variant_id: Uuid,
+    pub variant_version: String,
+    pub initial_secs: Option<i32>,
+    pub increment_secs: Option<i32>,
+}
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_games)]
+pub struct Game {
+    pub id: Uuid,
+    pub invitation_id: Uuid,
+    pub sender_id: Uuid,
+    pub receiver_id: Uuid,
+    pub variant_id: Uuid,
+    pub variant_version: String,
+    pub status: GameStatus,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Set, once both players reported the same result
+    pub outcome: Option<Outcome>,
+    pub termination: Option<Termination>,
+}
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_game_reports)]
+pub struct GameReport {
+    pub game_id: Uuid,
+    pub user_id: Uuid,
+    pub outcome: Outcome,
+    pub termination: Termination,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_game_reports)]
+struct NewGameReport {
+    game_id: Uuid,
+    user_id: Uuid,
+    outcome: Outcome,
+    termination: Termination,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_games)]
+struct NewGame {
+    invitation_id: Uuid,
+    sender_id: Uuid,
+    receiver_id: Uuid,
+    variant_id: Uuid,
+    variant_version: String,
+    peer_id: Vec<u8>,
+}
+
+impl GameInvitation {
+    pub fn time_control(&self) -> Option<TimeControl> {
+        Some(TimeControl {
+            initial_secs: self.initial_secs?,
+            increment_secs: self.increment_secs?,
+        })
+    }
+
+    pub async fn get(
+        conn: &mut AsyncPgConnection,
+        invitation_id: Uuid,
+    ) -> QueryResult<GameInvitation> {
+        use db_game_invitations::dsl::*;
+        game_invitations
+            .find(invitation_id)
+            .select(GameInvitation::as_select())
+            .first(conn)
+            .await
+    }
+
+    pub async fn insert(
+        conn: &mut AsyncPgConnection,
+        new_invitation: NewGameInvitation,
+    ) -> QueryResult<GameInvitation> {
+        use db_game_invitations::dsl::*;
+        insert_into(game_invitations)
+            .values(&new_invitation)
+            .returning(GameInvitation::as_returning())
+            .get_result(conn)
+            .await
+    }
+
+    /// Moves a pending invitation to its final status. Returns None, if it
+    /// isn't pending anymore.
+    pub async fn resolve(
+        conn: &mut AsyncPgConnection,
+        invitation_id: Uuid,
+        new_status: InvitationStatus,
+    ) -> QueryResult<Option<GameInvitation>> {
+        use db_game_invitations::dsl::*;
+        update(game_invitations)
+            .filter(id.eq(invitation_id))
+            .filter(status.eq(InvitationStatus::Pending))
+            .set((status.eq(new_status), resolved_at.eq(Utc::now())))
+            .returning(GameInvitation::as_returning())
+            .get_result(conn)
+            .await
+            .optional()
+    }
+
+    /// Accepts the pending invitation and starts its game with the version of
+    /// the variant, that the players agreed on. Returns None, if the
+    /// invitation isn't pending anymore.
+    pub async fn accept(
+        conn: &mut AsyncPgConnection,
+        invitation_id: Uuid,
+        peer_id: Vec<u8>,
+        variant_version: String,
+    ) -> QueryResult<Option<Game>> {
+        conn.transaction(|conn| {
+            async move {
+                let Some(invitation) =
+                    Self::resolve(conn, invitation_id, InvitationStatus::Accepted).await?
+                else {
+                    return Ok(None);
+                };
+                let new_game = NewGame {
+                    invitation_id,
+                    sender_id: invitation.sender_id,
+                    receiver_id: invitation.receiver_id,
+                    variant_id: invitation.variant_id,
+                    variant_version,
+                    peer_id,
+                };
+                let game = insert_into(db_games::table)
+                    .values(&new_game)
+                    .returning(Game::as_returning())
+                    .get_result(conn)
+                    .await?;
+                Ok(Some(game))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+impl Game {
+    pub fn is_player(&self, user_id: Uuid) -> bool {
+        self.sender_id == user_id || self.receiver_id == user_id
+    }
+
+    pub async fn get(conn: &mut AsyncPgConnection, game_id: Uuid) -> QueryResult<Game> {
+        use db_games::dsl::*;
+        games
+            .find(game_id)
+            .select(Game::as_select())
+            .first(conn)
+            .await
+    }
+
+    /// Stores the report of a player. The game finishes, once both players
+    /// reported the same result, and is disputed, if they didn't. Players can
+    /// correct their report until then. Finished games are rated right away.
+    pub async fn report_result(
+        conn: &mut AsyncPgConnection,
+        game_id: Uuid,
+        user_id: Uuid,
+        result: PlayerResult,
+        termination: Termination,
+    ) -> AppResult<(Game, ReportStatus)> {
+        conn.transaction(|conn| {
+            async move {
+                let game: Game = db_games::table
+                    .find(game_id)
+                    .for_update()
+                    .select(Game::as_select())
+                    .first(conn)
+                    .await?;
+                if !game.is_player(user_id) {
+                    return Err(AppError::Forbidden);
+                }
+                if game.status != GameStatus::InProgress {
+                    return Err(AppError::GameNotInProgress);
+                }
+                let outcome = Outcome::of(result, user_id == game.sender_id);
+                let report = NewGameReport {
+                    game_id,
+                    user_id,
+                    outcome,
+                    termination,
+                };
+                insert_into(db_game_reports::table)
+                    .values(&report)
+                    .on_conflict((db_game_reports::game_id, db_game_reports::user_id))
+                    .do_update()
+                    .set((
+                        db_game_reports::outcome.eq(outcome),
+                        db_game_reports::termination.eq(termination),
+                    ))
+                    .execute(conn)
+                    .await?;
+                let other_report: Option<GameReport> = db_game_reports::table
+                    .filter(db_game_reports::game_id.eq(game_id))
+                    .filter(db_game_reports::user_id.ne(user_id))
+                    .select(GameReport::as_select())
+                    .first(conn)
+                    .await
+                    .optional()?;
+                let Some(other_report) = other_report else {
+                    return Ok((game, ReportStatus::PendingConfirmation));
+                };
+                if other_report.outcome != outcome || other_report.termination != termination {
+                    insert_into(db_game_disputes::table)
+                        .values(db_game_disputes::game_id.eq(game_id))
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                        .await?;
+                    let game = update(db_games::table.find(game_id))
+                        .set(db_games::status.eq(GameStatus::Disputed))
+                        .returning(Game::as_returning())
+                        .get_result(conn)
+                        .await?;
+                    return Ok((game, ReportStatus::Disputed));
+                }
+                let game = update(db_games::table.find(game_id))
+                    .set((
+                        db_games::status.eq(GameStatus::Finished),
+                        db_games::outcome.eq(outcome),
+                        db_games::termination.eq(termination),
+                        db_games::finished_at.eq(Utc::now()),
+                    ))
+                    .returning(Game::as_returning())
+                    .get_result(conn)
+                    .await?;
+                Rating::rate_game(conn, &game).await?;
+                Ok((game, ReportStatus::Finished))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_by_invitation(
+        conn: &mut AsyncPgConnection,
+        query_invitation_id: Uuid,
+    ) -> QueryResult<Option<Game>> {
+        use db_games::dsl::*;
+        games
+            .filter(invitation_id.eq(query_invitation_id))
+            .select(Game::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+}
@@ -0,0 +1,105 @@
+use chrono::{DateTime, Utc};
+use diesel::{insert_into, prelude::*, update, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use super::schema::messages as db_messages;
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_messages)]
+pub struct Message {
+    pub id: i64,
+    pub sender_id: Uuid,
+    pub receiver_id: Uuid,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_messages)]
+pub struct NewMessage {
+    pub sender_id: Uuid,
+    pub receiver_id: Uuid,
+    pub body: String,
+}
+
+impl Message {
+    pub async fn insert(
+        conn: &mut AsyncPgConnection,
+        new_message: NewMessage,
+    ) -> QueryResult<Message> {
+        use db_messages::dsl::*;
+        insert_into(messages)
+            .values(&new_message)
+            .returning(Message::as_returning())
+            .get_result(conn)
+            .await
+    }
+
+    /// The messages between both users, newest first. Only messages older
+    /// than `before_id` are listed, if it's set.
+    pub async fn list_between(
+        conn: &mut AsyncPgConnection,
+        query_user_id: Uuid,
+        query_other_user_id: Uuid,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> QueryResult<Vec<Message>> {
+        use db_messages::dsl::*;
+        let mut query = messages
+            .filter(
+                sender_id
+                    .eq(query_user_id)
+                    .and(receiver_id.eq(query_other_user_id))
+                    .or(sender_id
+                        .eq(query_other_user_id)
+                        .and(receiver_id.eq(query_user_id))),
+            )
+            .into_boxed();
+        if let Some(before_id) = before_id {
+            query = query.filter(id.lt(before_id));
+        }
+        query
+            .order(id.desc())
+            .limit(limit)
+            .select(Message::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Marks the messages from the sender up to `up_to_id` as read by the
+    /// receiver. Returns how many messages were unread.
+    pub async fn mark_read(
+        conn: &mut AsyncPgConnection,
+        query_receiver_id: Uuid,
+        query_sender_id: Uuid,
+        up_to_id: i64,
+    ) -> QueryResult<usize> {
+        use db_messages::dsl::*;
+        update(messages)
+            .filter(sender_id.eq(query_sender_id))
+            .filter(receiver_id.eq(query_receiver_id))
+            .filter(id.le(up_to_id))
+            .filter(read_at.is_null())
+            .set(read_at.eq(Utc::now()))
+            .execute(conn)
+            .await
+    }
+
+    pub async fn count_unread(
+        conn: &mut AsyncPgConnection,
+        query_receiver_id: Uuid,
+        query_sender_id: Uuid,
+    ) -> QueryResult<i64> {
+        use db_messages::dsl::*;
+        messages
+            .filter(sender_id.eq(query_sender_id))
+            .filter(receiver_id.eq(query_receiver_id))
+            .filter(read_at.is_null())
+            .count()
+            .get_result(conn)
+            .await
+    }
+}
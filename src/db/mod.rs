@@ -2,7 +2,11 @@ pub mod users;
 pub mod db_conn;
 pub mod friend_requests;
 pub mod friends;
+pub mod games;
 pub mod lichess;
+pub mod messages;
+pub mod ratings;
 mod schema;
 mod extensions;
 pub mod extractor;
+pub mod variants;
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use diesel::{insert_into, prelude::*};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::glicko2;
+
+use super::{
+    games::{Game, Outcome},
+    schema::{rating_history as db_rating_history, ratings as db_ratings},
+    users::PublicUser,
+};
+
+/// The Glicko-2 rating of a user in a variant
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_ratings)]
+pub struct Rating {
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub variant_id: Uuid,
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    /// Rated games in the variant
+    pub games: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_rating_history)]
+pub struct RatingHistoryEntry {
+    /// The game, that led to the rating
+    pub game_id: Uuid,
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A public user with the ratings in all variants, the user played rated
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RatedUser {
+    #[serde(flatten)]
+    pub user: PublicUser,
+    /// Most played variants first
+    pub ratings: Vec<Rating>,
+}
+
+/// The ratings of several users, loaded with one query
+#[derive(Clone, Debug, Default)]
+pub struct UserRatings(HashMap<Uuid, Vec<Rating>>);
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_rating_history)]
+struct NewRatingHistoryEntry {
+    user_id: Uuid,
+    variant_id: Uuid,
+    game_id: Uuid,
+    rating: f64,
+    deviation: f64,
+    volatility: f64,
+}
+
+impl Rating {
+    fn glicko(&self) -> glicko2::Rating {
+        glicko2::Rating {
+            rating: self.rating,
+            deviation: self.deviation,
+            volatility: self.volatility,
+        }
+    }
+
+    pub async fn list_for_user(
+        conn: &mut AsyncPgConnection,
+        query_user_id: Uuid,
+    ) -> QueryResult<Vec<Rating>> {
+        use db_ratings::dsl::*;
+        ratings
+            .filter(user_id.eq(query_user_id))
+            .order(games.desc())
+            .select(Rating::as_select())
+            .load(conn)
+            .await
+    }
+
+    pub async fn list_for_users(
+        conn: &mut AsyncPgConnection,
+        query_user_ids: &[Uuid],
+    ) -> QueryResult<Vec<Rating>> {
+        use db_ratings::dsl::*;
+        ratings
+            .filter(user_id.eq_any(query_user_ids))
+            .order(games.desc())
+            .select(Rating::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// The ratings of the user in the variant after each game, oldest first
+    pub async fn history(
+        conn: &mut AsyncPgConnection,
+        query_user_id: Uuid,
+        query_variant_id: Uuid,
+    ) -> QueryResult<Vec<RatingHistoryEntry>> {
+        use db_rating_history::dsl::*;
+        rating_history
+            .filter(user_id.eq(query_user_id))
+            .filter(variant_id.eq(query_variant_id))
+            .order(id.asc())
+            .select(RatingHistoryEntry::as_select())
+            .load(conn)
+            .await
+    }
+
+    /// Rates both players of a finished game. Has to run in the transaction,
+    /// that finishes the game. Aborted games aren't rated.
+    pub async fn rate_game(conn: &mut AsyncPgConnection, game: &Game) -> QueryResult<()> {
+        let sender_score = match game.outcome {
+            Some(Outcome::SenderWon) => 1.0,
+            Some(Outcome::ReceiverWon) => 0.0,
+            Some(Outcome::Draw) => 0.5,
+            Some(Outcome::Aborted) | None => return Ok(()),
+        };
+        // Locked in a fixed order, so concurrent games can't deadlock
+        let current: Vec<Rating> = db_ratings::table
+            .filter(db_ratings::variant_id.eq(game.variant_id))
+            .filter(db_ratings::user_id.eq_any([game.sender_id, game.receiver_id]))
+            .order(db_ratings::user_id.asc())
+            .for_update()
+            .select(Rating::as_select())
+            .load(conn)
+            .await?;
+        let current_of = |user_id: Uuid| {
+            current
+                .iter()
+                .find(|rating| rating.user_id == user_id)
+                .map(Rating::glicko)
+                .unwrap_or_default()
+        };
+        let sender = current_of(game.sender_id);
+        let receiver = current_of(game.receiver_id);
+        let rated = [
+            (
+                game.sender_id,
+                glicko2::rate(
+                    sender,
+                    &[glicko2::Game {
+                        opponent: receiver,
+                        score: sender_score,
+                    }],
+                ),
+            ),
+            (
+                game.receiver_id,
+                glicko2::rate(
+                    receiver,
+                    &[glicko2::Game {
+                        opponent: sender,
+                        score: 1.0 - sender_score,
+                    }],
+                ),
+            ),
+        ];
+
+        for (player_id, new) in rated {
+            let glicko2::Rating {
+                rating,
+                deviation,
+                volatility,
+            } = new;
+            insert_into(db_ratings::table)
+                .values((
+                    db_ratings::user_id.eq(player_id),
+                    db_ratings::variant_id.eq(game.variant_id),
+                    db_ratings::rating.eq(rating),
+                    db_ratings::deviation.eq(deviation),
+                    db_ratings::volatility.eq(volatility),
+                    db_ratings::games.eq(1),
+                ))
+                .on_conflict((db_ratings::user_id, db_ratings::variant_id))
+                .do_update()
+                .set((
+                    db_ratings::rating.eq(rating),
+                    db_ratings::deviation.eq(deviation),
+                    db_ratings::volatility.eq(volatility),
+                    db_ratings::games.eq(db_ratings::games + 1),
+                ))
+                .execute(conn)
+                .await?;
+            let entry = NewRatingHistoryEntry {
+                user_id: player_id,
+                variant_id: game.variant_id,
+                game_id: game.id,
+                rating,
+                deviation,
+                volatility,
+            };
+            insert_into(db_rating_history::table)
+                .values(&entry)
+                .execute(conn)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl UserRatings {
+    pub async fn load(
+        conn: &mut AsyncPgConnection,
+        user_ids: impl IntoIterator<Item = Uuid>,
+    ) -> QueryResult<Self> {
+        let user_ids: Vec<Uuid> = user_ids.into_iter().collect();
+        let mut ratings_of: HashMap<Uuid, Vec<Rating>> = HashMap::new();
+        for rating in Rating::list_for_users(conn, &user_ids).await? {
+            ratings_of.entry(rating.user_id).or_default().push(rating);
+        }
+        Ok(UserRatings(ratings_of))
+    }
+
+    /// Adds the loaded ratings to the user. Users without ratings get none.
+    pub fn rate(&self, user: PublicUser) -> RatedUser {
+        let ratings = self.0.get(&user.id).cloned().unwrap_or_default();
+        RatedUser { user, ratings }
+    }
+}
+
+impl RatedUser {
+    pub async fn with_ratings(
+        conn: &mut AsyncPgConnection,
+        users: Vec<PublicUser>,
+    ) -> QueryResult<Vec<RatedUser>> {
+        let ratings = UserRatings::load(conn, users.iter().map(|user| user.id)).await?;
+        Ok(users.into_iter().map(|user| ratings.rate(user)).collect())
+    }
+}
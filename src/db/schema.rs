@@ -19,6 +19,59 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    game_disputes (id) {
+        id -> Int8,
+        game_id -> Uuid,
+        created_at -> Timestamptz,
+        resolved_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    game_invitations (id) {
+        id -> Uuid,
+        sender_id -> Uuid,
+        receiver_id -> Uuid,
+        variant_id -> Uuid,
+        variant_version -> Varchar,
+        status -> Varchar,
+        created_at -> Timestamptz,
+        resolved_at -> Nullable<Timestamptz>,
+        initial_secs -> Nullable<Int4>,
+        increment_secs -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    game_reports (id) {
+        id -> Int8,
+        game_id -> Uuid,
+        user_id -> Uuid,
+        outcome -> Varchar,
+        termination -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    games (id) {
+        id -> Uuid,
+        invitation_id -> Uuid,
+        sender_id -> Uuid,
+        receiver_id -> Uuid,
+        variant_id -> Uuid,
+        variant_version -> Varchar,
+        peer_id -> Bytea,
+        status -> Varchar,
+        created_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+        outcome -> Nullable<Varchar>,
+        termination -> Nullable<Varchar>,
+    }
+}
+
 diesel::table! {
     google_users (id) {
         id -> Varchar,
@@ -46,6 +99,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    messages (id) {
+        id -> Int8,
+        sender_id -> Uuid,
+        receiver_id -> Uuid,
+        body -> Varchar,
+        created_at -> Timestamptz,
+        read_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     peer_connections (id) {
         id -> Uuid,
@@ -55,6 +119,32 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    rating_history (id) {
+        id -> Int8,
+        user_id -> Uuid,
+        variant_id -> Uuid,
+        game_id -> Uuid,
+        rating -> Float8,
+        deviation -> Float8,
+        volatility -> Float8,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    ratings (user_id, variant_id) {
+        user_id -> Uuid,
+        variant_id -> Uuid,
+        rating -> Float8,
+        deviation -> Float8,
+        volatility -> Float8,
+        games -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
@@ -68,16 +158,59 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    variant_versions (id) {
+        id -> Int8,
+        variant_id -> Uuid,
+        version -> Varchar,
+        created_at -> Timestamptz,
+        withdrawn_at -> Nullable<Timestamptz>,
+        bundle -> Nullable<Bytea>,
+        bundle_format -> Nullable<Varchar>,
+        checksum -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    variants (id) {
+        id -> Uuid,
+        name -> Varchar,
+        description -> Varchar,
+        author_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::joinable!(game_disputes -> games (game_id));
+diesel::joinable!(game_reports -> games (game_id));
+diesel::joinable!(game_reports -> users (user_id));
+diesel::joinable!(games -> game_invitations (invitation_id));
 diesel::joinable!(google_users -> users (user_id));
 diesel::joinable!(lichess_users -> users (user_id));
 diesel::joinable!(peer_connections -> users (user_id));
+diesel::joinable!(rating_history -> games (game_id));
+diesel::joinable!(rating_history -> users (user_id));
+diesel::joinable!(rating_history -> variants (variant_id));
+diesel::joinable!(ratings -> users (user_id));
+diesel::joinable!(ratings -> variants (variant_id));
+diesel::joinable!(variant_versions -> variants (variant_id));
+diesel::joinable!(variants -> users (author_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     friend_requests,
     friends,
+    game_disputes,
+    game_invitations,
+    game_reports,
+    games,
     google_users,
     lichess_access_tokens,
     lichess_users,
+    messages,
     peer_connections,
+    rating_history,
+    ratings,
     users,
+    variant_versions,
+    variants,
 );
@@ -116,6 +116,19 @@ impl User {
         Ok(user)
     }
 
+    pub async fn get_public(
+        conn: &mut AsyncPgConnection,
+        query_uuid: Uuid,
+    ) -> AppResult<PublicUser> {
+        use db_users::dsl::users;
+        let user = users
+            .find(query_uuid)
+            .select(PublicUser::as_select())
+            .get_result(conn)
+            .await?;
+        Ok(user)
+    }
+
     pub async fn insert_with_google_id(
         conn: &mut AsyncPgConnection,
         user: NewUser,
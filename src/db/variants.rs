@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use diesel::{insert_into, prelude::*, update};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use uuid::Uuid;
+
+use super::{
+    extensions::text_enum::text_enum,
+    schema::{variant_versions as db_variant_versions, variants as db_variants},
+};
+
+text_enum! {
+    pub enum BundleFormat {
+        Json = "json",
+        Toml = "toml",
+    }
+}
+
+impl BundleFormat {
+    pub fn mime(&self) -> mime::Mime {
+        match self {
+            BundleFormat::Json => mime::APPLICATION_JSON,
+            BundleFormat::Toml => "application/toml".parse().unwrap(),
+        }
+    }
+}
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_variants)]
+pub struct Variant {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub author_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_variants)]
+pub struct NewVariant {
+    pub name: String,
+    pub description: String,
+    pub author_id: Uuid,
+}
+
+#[derive(Serialize, Queryable, Clone, Debug, Selectable)]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = db_variant_versions)]
+pub struct VariantVersion {
+    pub variant_id: Uuid,
+    /// A semver version
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    /// Withdrawn versions can't be used for new games
+    pub withdrawn_at: Option<DateTime<Utc>>,
+    /// Versions, that were published before rule bundles existed, have none
+    pub bundle_format: Option<BundleFormat>,
+    /// Hex encoded SHA-256 of the bundle
+    pub checksum: Option<String>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[diesel(table_name = db_variant_versions)]
+pub struct NewVariantVersion {
+    pub variant_id: Uuid,
+    pub version: String,
+    pub bundle: Vec<u8>,
+    pub bundle_format: BundleFormat,
+    pub checksum: String,
+}
+
+/// The rules of a variant version as they were uploaded
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    pub data: Vec<u8>,
+    pub format: BundleFormat,
+    pub checksum: String,
+}
+
+impl Variant {
+    pub async fn list(conn: &mut AsyncPgConnection) -> QueryResult<Vec<Variant>> {
+        use db_variants::dsl::*;
+        variants
+            .order(name.asc())
+            .select(Variant::as_select())
+            .load(conn)
+            .await
+    }
+
+    pub async fn get(conn: &mut AsyncPgConnection, variant_id: Uuid) -> QueryResult<Variant> {
+        use db_variants::dsl::*;
+        variants
+            .find(variant_id)
+            .select(Variant::as_select())
+            .first(conn)
+            .await
+    }
+
+    pub async fn insert(
+        conn: &mut AsyncPgConnection,
+        new_variant: NewVariant,
+    ) -> QueryResult<Variant> {
+        use db_variants::dsl::*;
+        insert_into(variants)
+            .values(&new_variant)
+            .returning(Variant::as_returning())
+            .get_result(conn)
+            .await
+    }
+}
+
+impl VariantVersion {
+    pub fn semver(&self) -> Option<semver::Version> {
+        semver::Version::parse(&self.version).ok()
+    }
+
+    /// The versions of the variant, highest first
+    pub async fn list_for_variant(
+        conn: &mut AsyncPgConnection,
+        query_variant_id: Uuid,
+    ) -> QueryResult<Vec<VariantVersion>> {
+        use db_variant_versions::dsl::*;
+        let mut versions = variant_versions
+            .filter(variant_id.eq(query_variant_id))
+            .select(VariantVersion::as_select())
+            .load(conn)
+            .await?;
+        versions.sort_by_cached_key(|v| std::cmp::Reverse(v.semver()));
+        Ok(versions)
+    }
+
+    pub async fn get(
+        conn: &mut AsyncPgConnection,
+        query_variant_id: Uuid,
+        query_version: &str,
+    ) -> QueryResult<Option<VariantVersion>> {
+        use db_variant_versions::dsl::*;
+        variant_versions
+            .filter(variant_id.eq(query_variant_id))
+            .filter(version.eq(query_version))
+            .select(VariantVersion::as_select())
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Returns None, if the version doesn't exist or has no bundle
+    pub async fn get_bundle(
+        conn: &mut AsyncPgConnection,
+        query_variant_id: Uuid,
+        query_version: &str,
+    ) -> QueryResult<Option<Bundle>> {
+        use db_variant_versions::dsl::*;
+        let row = variant_versions
+            .filter(variant_id.eq(query_variant_id))
+            .filter(version.eq(query_version))
+            .select((bundle, bundle_format, checksum))
+            .first::<(Option<Vec<u8>>, Option<BundleFormat>, Option<String>)>(conn)
+            .await
+            .optional()?;
+        let Some((Some(data), Some(format), Some(bundle_checksum))) = row else {
+            return Ok(None);
+        };
+        Ok(Some(Bundle {
+            data,
+            format,
+            checksum: bundle_checksum,
+        }))
+    }
+
+    /// Inserts the version, if it is higher than all versions of the variant.
+    /// Returns None otherwise. The variant is locked, so concurrent publishes
+    /// can't both pass the check.
+    pub async fn publish(
+        conn: &mut AsyncPgConnection,
+        new_version: NewVariantVersion,
+    ) -> QueryResult<Option<VariantVersion>> {
+        conn.transaction(|conn| {
+            async move {
+                db_variants::table
+                    .find(new_version.variant_id)
+                    .for_update()
+                    .select(db_variants::id)
+                    .first::<Uuid>(conn)
+                    .await?;
+                let versions = Self::list_for_variant(conn, new_version.variant_id).await?;
+                let latest = versions.iter().filter_map(VariantVersion::semver).max();
+                let version = semver::Version::parse(&new_version.version).ok();
+                if latest.is_some() && latest >= version {
+                    return Ok(None);
+                }
+                insert_into(db_variant_versions::table)
+                    .values(&new_version)
+                    .returning(VariantVersion::as_returning())
+                    .get_result(conn)
+                    .await
+                    .map(Some)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Returns None, if the version doesn't exist or was withdrawn before
+    pub async fn withdraw(
+        conn: &mut AsyncPgConnection,
+        query_variant_id: Uuid,
+        query_version: &str,
+    ) -> QueryResult<Option<VariantVersion>> {
+        use db_variant_versions::dsl::*;
+        update(variant_versions)
+            .filter(variant_id.eq(query_variant_id))
+            .filter(version.eq(query_version))
+            .filter(withdrawn_at.is_null())
+            .set(withdrawn_at.eq(Utc::now()))
+            .returning(VariantVersion::as_returning())
+            .get_result(conn)
+            .await
+            .optional()
+    }
+}
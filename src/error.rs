@@ -24,21 +24,53 @@ pub enum AppError {
     #[error("unknown")]
     SerdeJson(#[from] serde_json::error::Error),
     #[error("unknown")]
+    Redis(#[from] redis::RedisError),
+    #[error("unknown")]
     Unexpected,
     #[error("unauthorized")]
     Unauthorized,
+    #[error("forbidden")]
+    Forbidden,
+    #[error("websocket-ticket-used")]
+    WebsocketTicketUsed,
     #[error("already-friends")]
     AlreadyFriends,
     #[error("friend-request-doesnt-exist")]
     FriendRequestDoesntExist,
     #[error("friend-request-exists-in-other-direction")]
     FriendRequestExistsInOtherDirection,
+    #[error("not-friends")]
+    NotFriends,
     #[error("username-already-exists")]
     UsernameAlreadyExists,
     #[error("validate")]
     Validate(#[from] validator::ValidationErrors),
     #[error("actix-json-payload")]
     ActixJsonPayload(#[from] actix_web::error::JsonPayloadError),
+    #[error("receiver-offline")]
+    ReceiverOffline,
+    #[error("invitation-pending")]
+    InvitationPending,
+    #[error("invalid-time-control")]
+    InvalidTimeControl,
+    #[error("invalid-variant")]
+    InvalidVariant,
+    #[error("invalid-variant-version")]
+    InvalidVariantVersion,
+    #[error("invalid-variant-bundle")]
+    InvalidVariantBundle,
+    #[error("unknown-variant")]
+    UnknownVariant,
+    #[error("variant-withdrawn")]
+    VariantWithdrawn,
+    #[error("variant-name-already-exists")]
+    VariantNameAlreadyExists,
+    #[error("game-not-in-progress")]
+    GameNotInProgress,
+    #[error("websocket-session-not-found")]
+    WebsocketSessionNotFound,
+    #[error("shutting-down")]
+    ShuttingDown,
     #[error("websocket-{}", .0)]
     Websocket(#[from] WebsocketError),
 }
@@ -72,6 +104,7 @@ impl From<diesel::result::Error> for AppError {
             Some(DatabaseErrorKind::UniqueViolation) => {
                 match (table_name.as_deref(), column_name.as_deref()) {
                     (Some("users"), Some("user_name")) => return AppError::UsernameAlreadyExists,
+                    (Some("variants"), Some("name")) => return AppError::VariantNameAlreadyExists,
                     _ => (),
                 }
             }
@@ -93,14 +126,27 @@ impl actix_web::ResponseError for AppError {
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
             ActixWeb | ActixWebBlocking(_) | Bb8 | Reqwest(_) | Unexpected | SerdeJson(_)
-            | ActixJsonPayload(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            JwtParse(_) | Jwt(_) | OpenId | Unauthorized => StatusCode::UNAUTHORIZED,
+            | Redis(_) | ActixJsonPayload(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            JwtParse(_) | Jwt(_) | OpenId | Unauthorized | WebsocketTicketUsed => {
+                StatusCode::UNAUTHORIZED
+            }
             AlreadyFriends
             | FriendRequestDoesntExist
             | FriendRequestExistsInOtherDirection
             | UsernameAlreadyExists
             | Validate(_)
-            | Websocket(_) => StatusCode::BAD_REQUEST,
+            | InvalidTimeControl
+            | InvalidVariant
+            | InvalidVariantVersion
+            | InvalidVariantBundle
+            | UnknownVariant
+            | VariantWithdrawn
+            | VariantNameAlreadyExists => StatusCode::BAD_REQUEST,
+            Websocket(websocket_err) => websocket_err.status_code(),
+            ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            Forbidden | NotFriends => StatusCode::FORBIDDEN,
+            WebsocketSessionNotFound => StatusCode::NOT_FOUND,
+            ReceiverOffline | InvitationPending | GameNotInProgress => StatusCode::CONFLICT,
         }
     }
     fn error_response(&self) -> actix_web::HttpResponse<actix_web::body::BoxBody> {
@@ -0,0 +1,221 @@
+//! The Glicko-2 rating system as described by Mark Glickman in
+//! <http://www.glicko.net/glicko/glicko2.pdf>
+
+use std::f64::consts::PI;
+
+/// Converts between the Glicko and the Glicko-2 scale
+const SCALE: f64 = 173.7178;
+const DEFAULT_RATING: f64 = 1500.0;
+const DEFAULT_DEVIATION: f64 = 350.0;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+/// Constrains the change of the volatility over time
+const TAU: f64 = 0.5;
+/// Convergence tolerance of the volatility iteration
+const EPSILON: f64 = 0.000001;
+
+/// A rating on the Glicko scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+
+/// A game of the rating period against an opponent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Game {
+    pub opponent: Rating,
+    /// 1 for a win, 0.5 for a draw and 0 for a loss
+    pub score: f64,
+}
+
+/// Rates the player after a rating period with the given games. Without
+/// games, only the deviation grows.
+pub fn rate(player: Rating, games: &[Game]) -> Rating {
+    let mu = (player.rating - DEFAULT_RATING) / SCALE;
+    let phi = player.deviation / SCALE;
+    let sigma = player.volatility;
+
+    if games.is_empty() {
+        let phi = (phi * phi + sigma * sigma).sqrt();
+        return Rating {
+            deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+            ..player
+        };
+    }
+
+    let mut variance_inv = 0.0;
+    let mut improvement_sum = 0.0;
+    for game in games {
+        let mu_j = (game.opponent.rating - DEFAULT_RATING) / SCALE;
+        let phi_j = game.opponent.deviation / SCALE;
+        let g = g(phi_j);
+        let e = expected_score(mu, mu_j, g);
+        variance_inv += g * g * e * (1.0 - e);
+        improvement_sum += g * (game.score - e);
+    }
+    let v = 1.0 / variance_inv;
+    let delta = v * improvement_sum;
+
+    let sigma = volatility(phi, sigma, v, delta);
+    let phi_star = (phi * phi + sigma * sigma).sqrt();
+    let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let mu = mu + phi * phi * improvement_sum;
+
+    Rating {
+        rating: mu * SCALE + DEFAULT_RATING,
+        deviation: (phi * SCALE).min(DEFAULT_DEVIATION),
+        volatility: sigma,
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected_score(mu: f64, mu_j: f64, g: f64) -> f64 {
+    1.0 / (1.0 + (-g * (mu - mu_j)).exp())
+}
+
+/// The new volatility found by the Illinois algorithm
+fn volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+    (big_a / 2.0).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    #[test]
+    fn example_of_the_paper() {
+        let games = [
+            Game {
+                opponent: rating(1400.0, 30.0),
+                score: 1.0,
+            },
+            Game {
+                opponent: rating(1550.0, 100.0),
+                score: 0.0,
+            },
+            Game {
+                opponent: rating(1700.0, 300.0),
+                score: 0.0,
+            },
+        ];
+        let new = rate(rating(1500.0, 200.0), &games);
+        assert_close(new.rating, 1464.06, 0.01);
+        assert_close(new.deviation, 151.52, 0.01);
+        assert_close(new.volatility, 0.05999, 0.00001);
+    }
+
+    #[test]
+    fn no_games_only_grow_the_deviation() {
+        let player = rating(1600.0, 100.0);
+        let new = rate(player, &[]);
+        assert_eq!(new.rating, player.rating);
+        assert_eq!(new.volatility, player.volatility);
+        assert!(new.deviation > player.deviation);
+    }
+
+    #[test]
+    fn deviation_is_capped() {
+        let new = rate(Rating::default(), &[]);
+        assert_eq!(new.deviation, DEFAULT_DEVIATION);
+    }
+
+    #[test]
+    fn winner_gains_what_loser_loses_between_equals() {
+        let player = Rating::default();
+        let won = rate(
+            player,
+            &[Game {
+                opponent: player,
+                score: 1.0,
+            }],
+        );
+        let lost = rate(
+            player,
+            &[Game {
+                opponent: player,
+                score: 0.0,
+            }],
+        );
+        assert!(won.rating > player.rating);
+        assert!(lost.rating < player.rating);
+        assert_close(
+            won.rating - player.rating,
+            player.rating - lost.rating,
+            1e-9,
+        );
+        assert!(won.deviation < player.deviation);
+    }
+
+    #[test]
+    fn draw_between_equals_keeps_the_rating() {
+        let player = rating(1700.0, 80.0);
+        let new = rate(
+            player,
+            &[Game {
+                opponent: player,
+                score: 0.5,
+            }],
+        );
+        assert_close(new.rating, player.rating, 1e-9);
+    }
+}
@@ -1,10 +1,11 @@
-use api::websocket;
+use api::websocket::{self, cluster::Cluster, Websockets};
 use db::db_conn::DbPool;
 use diesel_async::{pooled_connection::AsyncDieselConnectionManager, AsyncPgConnection};
 use dotenvy::dotenv;
 extern crate bb8;
 extern crate diesel;
-#[macro_use]
+// Not the whole crate, its test attribute would shadow the one of std
+#[macro_use(get, post, delete)]
 extern crate actix_web;
 #[macro_use]
 extern crate serde_with;
@@ -31,6 +32,8 @@ mod app_json;
 mod app_result;
 mod db;
 mod error;
+mod glicko2;
+mod redis_db;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -38,6 +41,7 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env_logger::init_from_env(Env::default().default_filter_or("debug"));
     let database_url = env::var("DATABASE_URL").unwrap();
+    let redis_url = env::var("REDIS_URL").expect("REDIS_URL not set!");
     let actix_host = env::var("ACTIX_HOST").expect("ACTIX_HOST not set!");
     debug!("actix web host: {actix_host}");
     let actix_port = env::var("ACTIX_PORT").expect("ACTIX_PORT not set!");
@@ -48,12 +52,25 @@ async fn main() -> std::io::Result<()> {
         .build(manager)
         .await
         .expect("Failed to create pool.");
+    let redis_client = redis::Client::open(redis_url).expect("Invalid redis url.");
+    let cluster = Cluster::connect(redis_client.clone())
+        .await
+        .expect("Failed to connect to redis.");
+    debug!("websocket instance id: {}", cluster.instance_id);
+    let redis_data = Data::new(redis_client);
+
+    let websocket_config = websocket::Config::from_env();
+    let shutdown_timeout = websocket_config.shutdown_timeout;
+    let websockets_data = Data::new(Websockets::new(pool.clone(), websocket_config, cluster));
+    websocket::reaper::spawn(websockets_data.clone().into_inner());
+    websocket::cluster::spawn(websockets_data.clone().into_inner());
     let pool_data = Data::new(pool);
 
     let json_config = JsonConfig::default();
     let json_config_data = Data::new(json_config);
+    let websockets = websockets_data.clone().into_inner();
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let app = App::new()
             .service(
                 // Health check
@@ -62,8 +79,12 @@ async fn main() -> std::io::Result<()> {
             .configure(api::auth::config)
             .configure(api::users::config)
             .configure(api::games::config)
+            .configure(api::variants::config)
             .configure(api::websocket::config)
+            .configure(api::admin::config)
             .app_data(pool_data.clone())
+            .app_data(websockets_data.clone())
+            .app_data(redis_data.clone())
             .app_data(Data::new(reqwest::Client::new()))
             .app_data(json_config_data.clone())
             .wrap(Logger::default());
@@ -72,6 +93,18 @@ async fn main() -> std::io::Result<()> {
         app.wrap(Cors::permissive())
     })
     .bind(format!("{actix_host}:{actix_port}"))?
-    .run()
-    .await
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
+    .run();
+
+    // The websocket sessions are drained before the workers stop, so that the
+    // clients get a close frame
+    let server_handle = server.handle();
+    actix_web::rt::spawn(async move {
+        websocket::shutdown::signal_received().await;
+        log::info!("Shutting down");
+        websocket::shutdown::drain(&websockets).await;
+        server_handle.stop(true).await;
+    });
+    server.await
 }
@@ -1,2 +1,5 @@
 pub mod extractor;
-pub mod peer_connections;
+pub mod pending_invitations;
+pub mod presence;
+pub mod websocket_sessions;
+pub mod websocket_tickets;
@@ -0,0 +1,49 @@
+use redis::{AsyncCommands, SetExpiry, SetOptions};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// The instance of the server, that waits for the answer to an invitation
+pub struct InvitationInstance;
+
+impl InvitationInstance {
+    pub async fn set(
+        conn: &mut redis::aio::MultiplexedConnection,
+        sender_id: Uuid,
+        receiver_id: Uuid,
+        request_id: i32,
+        instance_id: Uuid,
+        ttl_secs: u64,
+    ) -> Result<(), AppError> {
+        let options = SetOptions::default().with_expiration(SetExpiry::EX(ttl_secs));
+        let key = key(sender_id, receiver_id, request_id);
+        let _: () = conn
+            .set_options(key, instance_id.to_string(), options)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(
+        conn: &mut redis::aio::MultiplexedConnection,
+        sender_id: Uuid,
+        receiver_id: Uuid,
+        request_id: i32,
+    ) -> Result<Option<Uuid>, AppError> {
+        let instance_id: Option<String> = conn.get(key(sender_id, receiver_id, request_id)).await?;
+        Ok(instance_id.and_then(|instance_id| Uuid::parse_str(&instance_id).ok()))
+    }
+
+    pub async fn remove(
+        conn: &mut redis::aio::MultiplexedConnection,
+        sender_id: Uuid,
+        receiver_id: Uuid,
+        request_id: i32,
+    ) -> Result<(), AppError> {
+        let _: u64 = conn.del(key(sender_id, receiver_id, request_id)).await?;
+        Ok(())
+    }
+}
+
+fn key(sender_id: Uuid, receiver_id: Uuid, request_id: i32) -> String {
+    format!("invitations:instance:{sender_id}:{receiver_id}:{request_id}")
+}
@@ -0,0 +1,30 @@
+use redis::{AsyncCommands, SetExpiry, SetOptions};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// The presence of a user, that was last published to their friends
+pub struct PublishedPresence;
+
+impl PublishedPresence {
+    /// Stores the presence and returns the one, that was published before.
+    /// It expires, so that one left behind by a crash is forgotten.
+    pub async fn swap(
+        conn: &mut redis::aio::MultiplexedConnection,
+        user_id: Uuid,
+        presence: &str,
+        ttl_secs: u64,
+    ) -> Result<Option<String>, AppError> {
+        let options = SetOptions::default()
+            .get(true)
+            .with_expiration(SetExpiry::EX(ttl_secs));
+        let previous: Option<String> = conn
+            .set_options(key_for_user(user_id), presence, options)
+            .await?;
+        Ok(previous)
+    }
+}
+
+fn key_for_user(user_id: Uuid) -> String {
+    format!("users:presence:{user_id}")
+}
@@ -0,0 +1,93 @@
+use chrono::{DateTime, Utc};
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// A websocket session and the instance of the server, that holds it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebsocketSessionEntry {
+    pub instance_id: Uuid,
+    pub session_id: Uuid,
+}
+
+impl WebsocketSessionEntry {
+    /// Stores the session with the time its client was last heard of
+    pub async fn upsert(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+        user_id: Uuid,
+        last_active: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let key = key_for_user(user_id);
+        let score = last_active.timestamp();
+        let _: u64 = conn.zadd(key, self.member(), score).await?;
+        Ok(())
+    }
+
+    pub async fn remove(
+        &self,
+        conn: &mut redis::aio::MultiplexedConnection,
+        user_id: Uuid,
+    ) -> Result<(), AppError> {
+        let key = key_for_user(user_id);
+        let _: u64 = conn.zrem(key, self.member()).await?;
+        Ok(())
+    }
+
+    /// Sessions of the user, that were active after `dropoff`, most recent
+    /// first. Older entries are removed.
+    pub async fn list_for_user(
+        conn: &mut redis::aio::MultiplexedConnection,
+        user_id: Uuid,
+        dropoff: DateTime<Utc>,
+    ) -> Result<Vec<WebsocketSessionEntry>, AppError> {
+        let key = key_for_user(user_id);
+        let dropoff_score = dropoff.timestamp();
+        let _: u64 = conn.zrembyscore(&key, "-inf", dropoff_score - 1).await?;
+        let members: Vec<String> = conn.zrevrange(&key, 0, -1).await?;
+        let entries = members
+            .iter()
+            .filter_map(|member| Self::from_member(member))
+            .collect();
+        Ok(entries)
+    }
+
+    /// When any session of the user was last active, if it was after
+    /// `dropoff`
+    pub async fn last_active_of_user(
+        conn: &mut redis::aio::MultiplexedConnection,
+        user_id: Uuid,
+        dropoff: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, AppError> {
+        let key = key_for_user(user_id);
+        let latest: Vec<(String, i64)> = conn.zrevrange_withscores(&key, 0, 0).await?;
+        let last_active = latest
+            .into_iter()
+            .next()
+            .and_then(|(_, score)| DateTime::from_timestamp(score, 0))
+            .filter(|last_active| *last_active >= dropoff);
+        Ok(last_active)
+    }
+
+    fn member(&self) -> String {
+        let WebsocketSessionEntry {
+            instance_id,
+            session_id,
+        } = self;
+        format!("{instance_id}:{session_id}")
+    }
+
+    fn from_member(member: &str) -> Option<Self> {
+        let (instance_id, session_id) = member.split_once(':')?;
+        Some(WebsocketSessionEntry {
+            instance_id: instance_id.parse().ok()?,
+            session_id: session_id.parse().ok()?,
+        })
+    }
+}
+
+fn key_for_user(user_id: Uuid) -> String {
+    format!("users:websocket_sessions:{user_id}")
+}
@@ -0,0 +1,26 @@
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// Remembers the websocket tickets, that were used already
+pub struct UsedWebsocketTicket;
+
+impl UsedWebsocketTicket {
+    /// Marks the ticket as used. Returns false, if it was used before.
+    pub async fn consume(
+        conn: &mut redis::aio::MultiplexedConnection,
+        ticket_id: Uuid,
+        ttl_secs: u64,
+    ) -> Result<bool, AppError> {
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(ttl_secs));
+        let result: Option<String> = conn.set_options(key(ticket_id), 1, options).await?;
+        Ok(result.is_some())
+    }
+}
+
+fn key(ticket_id: Uuid) -> String {
+    format!("websocket_tickets:used:{ticket_id}")
+}
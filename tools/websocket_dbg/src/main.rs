@@ -1,15 +1,11 @@
 use std::{env, sync::Arc};
 
-use dotenvy::dotenv;
 use env_logger::Env;
-use futures_util::{
-    future, pin_mut,
-    stream::{SplitSink, SplitStream},
-    SinkExt, StreamExt,
-};
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use p2pcv_protobuf::{
-    client_to_server::{self, msg::C2s, NewGame},
-    server_to_client::{self, msg::S2c, NewGameEvent},
+    client_to_server::{self, msg::C2s, Hello, NewGame},
+    server_to_client::{self, msg::S2c},
+    PROTOCOL_VERSION,
 };
 use prost::Message;
 use tokio::{net::TcpStream, sync::Mutex};
@@ -45,7 +41,7 @@ async fn main() -> anyhow::Result<()> {
     let variant_id = uuid::uuid!("fa21a473-dead-beef-b116-b0000000b135");
 
     let handle = tokio::spawn(async move {
-        let (write, read) = splitted2.as_ref();
+        let (_, read) = splitted2.as_ref();
         let mut read_lock = read.lock().await;
         while let Some(message) = read_lock.next().await {
             drop(read_lock);
@@ -58,7 +54,7 @@ async fn main() -> anyhow::Result<()> {
                     continue;
                 }
             };
-            if let Err(err) = handle_server_message(write, message).await {
+            if let Err(err) = handle_server_message(message).await {
                 log::error!("Error in message handler!");
                 log::error!("{err}");
                 read_lock = read.lock().await;
@@ -68,16 +64,25 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    let (write, _) = splitted.as_ref();
+
+    let hello = Hello {
+        protocol_version: PROTOCOL_VERSION,
+        client_build: format!("websocket_dbg {}", env!("CARGO_PKG_VERSION")),
+        features: Vec::new(),
+    };
+    send_c2s(write, 1, C2s::Hello(hello)).await?;
+
     let new_game_request = NewGame {
         receiver_user_id: receiver_user_id.as_bytes().to_vec(),
         variant_id: variant_id.as_bytes().to_vec(),
         variant_version: "1.0.0".to_owned(),
+        time_control: None,
+        supported_versions: Vec::new(),
     };
     let request = C2s::NewGame(new_game_request);
 
-    let (write, _) = splitted.as_ref();
-
-    send_c2s(write, request).await?;
+    send_c2s(write, 2, request).await?;
 
     ping_server(write).await?;
 
@@ -105,25 +110,29 @@ async fn ping_server(
 
 async fn send_c2s(
     write: &Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>>,
+    id: i32,
     message: C2s,
 ) -> anyhow::Result<()> {
+    let msg = client_to_server::Msg {
+        id,
+        c2s: Some(message),
+    };
     let mut buf = Vec::new();
-    message.encode(&mut buf);
+    msg.encode(&mut buf)?;
     send_to_server(write, tungstenite::Message::Binary(buf)).await?;
     Ok(())
 }
 
-async fn handle_server_message(
-    write: &Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>>,
-    msg: tungstenite::Message,
-) -> anyhow::Result<()> {
+async fn handle_server_message(msg: tungstenite::Message) -> anyhow::Result<()> {
     match msg {
         tungstenite::Message::Binary(msg) => {
-            let server_to_client::Msg { id, s2c } = server_to_client::Msg::decode(msg.as_ref())?;
+            let server_to_client::Msg { id, s2c, seq } =
+                server_to_client::Msg::decode(msg.as_ref())?;
             let Some(s2c) = s2c else {
                 return Err(anyhow::anyhow!("Server message is empty"));
             };
-            handle_s2c(write, s2c).await?;
+            log::debug!("Message id: {id} (Seq: {seq})");
+            handle_s2c(s2c).await?;
         }
         tungstenite::Message::Pong(pong) => {
             log::debug!("{}", String::from_utf8_lossy(&pong));
@@ -145,10 +154,7 @@ async fn handle_server_message(
     Ok(())
 }
 
-async fn handle_s2c(
-    write: &Mutex<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tungstenite::Message>>,
-    msg: S2c,
-) -> anyhow::Result<()> {
+async fn handle_s2c(msg: S2c) -> anyhow::Result<()> {
     match msg {
         S2c::NewGameEvent(e) => {
             log::debug!("{e:?}")
@@ -156,6 +162,60 @@ async fn handle_s2c(
         S2c::NewGameResponse(r) => {
             log::debug!("{r:?}")
         }
+        S2c::NewGameEventCancelled(c) => {
+            log::debug!("{c:?}")
+        }
+        S2c::NewGameEventResponseError(e) => {
+            log::debug!("{e:?}")
+        }
+        S2c::SdpOffer(o) => {
+            log::debug!("{o:?}")
+        }
+        S2c::SdpAnswer(a) => {
+            log::debug!("{a:?}")
+        }
+        S2c::IceCandidate(c) => {
+            log::debug!("{c:?}")
+        }
+        S2c::SignalingError(e) => {
+            log::debug!("{e:?}")
+        }
+        S2c::Error(e) => {
+            log::error!("{e:?}")
+        }
+        S2c::FriendPresence(p) => {
+            log::debug!("{p:?}")
+        }
+        S2c::SessionStarted(s) => {
+            log::debug!("{s:?}")
+        }
+        S2c::HelloResponse(h) => {
+            log::debug!("{h:?}")
+        }
+        S2c::GoingAway(g) => {
+            log::info!("{g:?}")
+        }
+        S2c::RateLimited(r) => {
+            log::error!("{r:?}")
+        }
+        S2c::FriendRequestEvent(e) => {
+            log::debug!("{e:?}")
+        }
+        S2c::FriendRemoved(r) => {
+            log::debug!("{r:?}")
+        }
+        S2c::ChatMessage(m) => {
+            log::debug!("{m:?}")
+        }
+        S2c::ChatRead(r) => {
+            log::debug!("{r:?}")
+        }
+        S2c::NewGameAccepted(a) => {
+            log::debug!("{a:?}")
+        }
+        S2c::GameResult(r) => {
+            log::debug!("{r:?}")
+        }
     }
     Ok(())
 }